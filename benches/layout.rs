@@ -0,0 +1,101 @@
+//! Compares the `Dense` and `Padded` cell layouts under contention.
+//!
+//! Run with `cargo bench --bench layout`.
+//! Padding pays off once producers and consumers run on different cores,
+//! on a single core both layouts perform about the same.
+
+#![feature(test)]
+
+extern crate test;
+
+use nblfq::{CellLayout, Dense, HeapBackedQueue, InlineQueue, Padded};
+use std::{hint::black_box, thread::scope};
+use test::Bencher;
+
+/// Items transferred by every producer per iteration
+const COUNT: usize = 10_000;
+const CAP: usize = 64;
+
+/// Transfers `COUNT` items from each of `threads` producers to `threads` consumers.
+fn transfer(threads: usize, push: impl Fn(usize) -> bool + Sync, pop: impl Fn() -> bool + Sync) {
+    scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    while !pop() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while !push(i) {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn heap_backed<L: CellLayout>(b: &mut Bencher, threads: usize) {
+    let q = HeapBackedQueue::<usize, _, L>::with_layout(CAP);
+    b.iter(|| {
+        transfer(
+            threads,
+            |i| q.push(i).is_ok(),
+            || black_box(q.pop()).is_some(),
+        )
+    });
+}
+
+fn inline<L: CellLayout>(b: &mut Bencher, threads: usize) {
+    let q: InlineQueue<usize, L> = InlineQueue::with_layout(CAP);
+    b.iter(|| {
+        transfer(
+            threads,
+            |i| q.push(i).is_ok(),
+            || black_box(q.pop()).is_some(),
+        )
+    });
+}
+
+#[bench]
+fn heap_backed_spsc_dense(b: &mut Bencher) {
+    heap_backed::<Dense>(b, 1);
+}
+
+#[bench]
+fn heap_backed_spsc_padded(b: &mut Bencher) {
+    heap_backed::<Padded>(b, 1);
+}
+
+#[bench]
+fn heap_backed_mpmc_dense(b: &mut Bencher) {
+    heap_backed::<Dense>(b, 4);
+}
+
+#[bench]
+fn heap_backed_mpmc_padded(b: &mut Bencher) {
+    heap_backed::<Padded>(b, 4);
+}
+
+#[bench]
+fn inline_spsc_dense(b: &mut Bencher) {
+    inline::<Dense>(b, 1);
+}
+
+#[bench]
+fn inline_spsc_padded(b: &mut Bencher) {
+    inline::<Padded>(b, 1);
+}
+
+#[bench]
+fn inline_mpmc_dense(b: &mut Bencher) {
+    inline::<Dense>(b, 4);
+}
+
+#[bench]
+fn inline_mpmc_padded(b: &mut Bencher) {
+    inline::<Padded>(b, 4);
+}
@@ -1,5 +1,5 @@
 use core::{
-    fmt::Debug,
+    fmt::{self, Debug},
     iter,
     marker::PhantomData,
     ptr::null,
@@ -9,20 +9,32 @@ use core::{
 use cfg_if::cfg_if;
 
 use crate::{
-    components::{self, ItemInner, PtrType},
-    utils::{comp, prev},
+    backoff::{Backoff, Spin},
+    components::{self, CellLayout, DefaultStorage, Dense, Storage},
+    error::{PopError, PushError},
+    utils::{CachePadded, comp},
 };
 
 cfg_if! {
     if #[cfg(feature = "alloc")] {
+        pub use borrow::*;
         pub use heap_based::*;
         pub use heapless::*;
+        pub use arc::*;
+        pub use index::*;
+        pub use inline::*;
+        pub use raw::*;
     } else {
+        pub use borrow::*;
         pub use heapless::*;
     }
 }
 
-pub(crate) struct ArrayQueue<T, B: components::Buffer<T>> {
+/// The core NBLFQ ring.
+///
+/// `head`, `tail` and `producers` are written by different threads, so each of them is padded to a cache line of its own.
+/// Failed cell CAS's and busy slots are retried after waiting with `W`.
+pub(crate) struct ArrayQueue<T, B: components::Buffer<T>, W: Backoff = Spin> {
     /// The buffer of the queue holding Item<T>'s
     buffer: B,
     /// The head of the queue.
@@ -30,128 +42,580 @@ pub(crate) struct ArrayQueue<T, B: components::Buffer<T>> {
     /// This value indicates the next slot that can be pushed to.
     ///
     /// This value may be stale and must be checked for critical operations.
-    head: AtomicUsize,
+    head: CachePadded<AtomicUsize>,
     /// The tail of the queue.
     ///
     /// This value indicates the next slot that can be popped from.
     ///
     /// This value may be stale and must be checked for critical operations.
-    tail: AtomicUsize,
+    tail: CachePadded<AtomicUsize>,
+    /// The number of producers currently pushing, `CLOSED` is set once the queue has been closed.
+    producers: CachePadded<AtomicUsize>,
     _data: PhantomData<*const T>,
+    _backoff: PhantomData<fn() -> W>,
 }
 
-impl<T, B: components::Buffer<T>> ArrayQueue<T, B> {
-    fn new_in(buffer: B) -> Self {
+/// The (index, count) of the cell the only producer or consumer of a queue claims next,
+/// or None if it has to be found by a scan of the buffer.
+#[cfg_attr(not(feature = "alloc"), allow(unused))]
+pub(crate) type Cursor = Option<(usize, u64)>;
+
+/// Set in `ArrayQueue::producers` once the queue no longer accepts pushes.
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+impl<T, B: components::Buffer<T>, W: Backoff> ArrayQueue<T, B, W> {
+    /// The number of distinct round counts of the buffer's storage
+    const MAX_W: u64 = components::Item::<T, B::Storage>::MAX_W;
+
+    pub(crate) const fn new_in(buffer: B) -> Self {
         Self {
             buffer,
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            producers: CachePadded::new(AtomicUsize::new(0)),
             _data: PhantomData,
+            _backoff: PhantomData,
         }
     }
 }
 
-impl<T, B: components::Buffer<T>> ArrayQueue<T, B> {
+impl<T, B: components::Buffer<T>, W: Backoff> ArrayQueue<T, B, W> {
     /// pop the last item, if an item is contained
-    pub fn pop(&self) -> Option<*const T> {
+    pub(crate) fn pop(&self) -> Option<*const T> {
+        let mut backoff = W::default();
         loop {
-            let mut tail = self.tail.load(Ordering::Acquire);
-            let mut prev_idx = prev(tail, self.buffer.len());
-            let prev_item = self.buffer.inner().get(prev_idx)?;
-            let mut current_item = self.buffer.inner().get(tail)?;
-            let (mut prev_count, mut prev_ptr) = prev_item.components();
-            let (mut current_count, mut current_ptr) = current_item.components();
-
-            while comp(
-                prev_idx,
-                prev_count,
-                tail,
-                current_count,
-                PtrType::<T>::MAX_W,
-            ) {
-                tail = (tail + 1) % self.buffer.len();
-                prev_idx = prev(tail, self.buffer.len());
-                current_item = self.buffer.inner().get(tail)?;
-                (prev_count, prev_ptr, (current_count, current_ptr)) =
-                    (current_count, current_ptr, current_item.components());
+            let (tail, count, item) = self.find_tail()?;
+            let next_count = (count + 1) % Self::MAX_W;
+
+            if self
+                .cell(tail)
+                .cmpxchg(item, count, null(), next_count)
+                .is_ok()
+            {
+                self.publish_tail(tail);
+                return Some(item);
             }
+            backoff.wait();
+        }
+    }
 
-            if prev_ptr.is_null() && current_ptr.is_null() {
-                // empty queue
-                return None;
+    /// Returns the (index, count, ptr) of the oldest cell without popping it, or None if the queue is empty.
+    pub(crate) fn peek(&self) -> Option<(usize, u64, *const T)> {
+        self.find_tail()
+    }
+
+    /// Returns the (index, count) the next push claims, which every item currently in the queue precedes.
+    pub(crate) fn end(&self) -> (usize, u64) {
+        let mut head = self.head.load(Ordering::Acquire);
+        match self.find_head(&mut head) {
+            Ok(count) => (head, count),
+            // the next push follows the newest item, which precedes the oldest cell
+            Err((count, _)) => (head, (count + 1) % Self::MAX_W),
+        }
+    }
+
+    /// Pops the oldest item, if it precedes `end`.
+    ///
+    /// Items pushed after `end` has been taken never precede it,
+    /// so repeated calls with the same `end` pop at most `capacity` items, regardless of concurrent pushes.
+    pub(crate) fn pop_before(&self, end: (usize, u64)) -> Option<*const T> {
+        self.pop_if(|idx, count, _| comp(idx, count, end.0, end.1, Self::MAX_W))
+    }
+
+    /// Walks the cells from the oldest item towards the head, returning the (index, count, ptr) of every item.
+    ///
+    /// The walk continues as long as the next cell holds the item pushed after the previous one, and visits every cell at most once.
+    /// Items popped or pushed during the walk may or may not be returned, so this is a best-effort snapshot.
+    pub(crate) fn items(&self) -> impl Iterator<Item = (usize, u64, *const T)> {
+        let mut next = self.find_tail();
+        let mut remaining = self.capacity();
+        iter::from_fn(move || {
+            let (idx, count, item) = next.take()?;
+            remaining -= 1;
+            let following = self.buffer.next(idx);
+            let (next_count, next_item) = self.cell(following).components();
+            if remaining > 0
+                && next_count == self.following_count(following, count)
+                && !next_item.is_null()
+            {
+                next = Some((following, next_count, next_item));
             }
+            Some((idx, count, item))
+        })
+    }
+
+    /// Formats the queue as `name` with its capacity, the head and tail hints and the round count of every cell,
+    /// preceded by its `items`, if they are given.
+    pub(crate) fn fmt_as(
+        &self,
+        name: &str,
+        items: Option<&dyn Debug>,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        let counts = fmt::from_fn(|f| {
+            f.debug_list()
+                .entries((0..self.capacity()).map(|idx| self.cell(idx).components().0))
+                .finish()
+        });
+        let mut f = f.debug_struct(name);
+        if let Some(items) = items {
+            f.field("items", items);
+        }
+        f.field("capacity", &self.capacity())
+            .field("head", &self.head.load(Ordering::Relaxed))
+            .field("tail", &self.tail.load(Ordering::Relaxed))
+            .field("counts", &counts)
+            .finish()
+    }
+
+    /// Indicates whether cell `idx` still holds `item` with `count`, i.e. whether an item returned by `peek` has not been popped yet.
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn holds(&self, idx: usize, count: u64, item: *const T) -> bool {
+        self.cell(idx).components() == (count, item)
+    }
 
-            let next_count = (current_count + 1) % PtrType::<T>::MAX_W;
+    /// Pops the oldest item, if `pred` holds for its (index, count, ptr).
+    ///
+    /// The cell is emptied with the same CAS as in pop(), against the count `pred` has been called with,
+    /// so no other consumer can pop the item between the check and the pop.
+    /// If the CAS fails, `pred` is called again for the new oldest item.
+    pub(crate) fn pop_if(
+        &self,
+        mut pred: impl FnMut(usize, u64, *const T) -> bool,
+    ) -> Option<*const T> {
+        let mut backoff = W::default();
+        loop {
+            let (tail, count, item) = self.find_tail()?;
+            if !pred(tail, count, item) {
+                return None;
+            }
+            let next_count = (count + 1) % Self::MAX_W;
 
-            if let Ok((_, item)) =
-                current_item.cmpxchg(current_ptr, current_count, null(), next_count)
+            if self
+                .cell(tail)
+                .cmpxchg(item, count, null(), next_count)
+                .is_ok()
             {
-                self.tail
-                    .store((tail + 1) % self.buffer.len(), Ordering::Release);
+                self.publish_tail(tail);
                 return Some(item);
             }
+            backoff.wait();
         }
     }
 
     /// Attempts to push an item into the queue.
-    /// Returns the item as an error if the queue is full.
-    fn push(&self, item: *const T) -> Result<(), *const T> {
+    /// Returns the item as an error if the queue is full or closed.
+    pub(crate) fn push(&self, item: *const T) -> Result<(), PushError<*const T>> {
+        if !self.enter() {
+            return Err(PushError::Closed(item));
+        }
+        let mut backoff = W::default();
         let mut head = self.head.load(Ordering::Acquire);
-        loop {
-            let (count, prev_ptr) = loop {
-                let prev_idx = prev(head, self.buffer.len());
-                let current_item = self.buffer.inner().get(head).ok_or(item)?;
-                let prev_item = self.buffer.inner().get(prev_idx).ok_or(item)?;
-                let (prev_count, prev_ptr) = prev_item.components();
-                let (current_count, current_ptr) = current_item.components();
-
-                if !prev_ptr.is_null() && current_ptr.is_null() {
-                    break (prev_count, prev_ptr);
-                }
+        let pushed = loop {
+            let Ok(count) = self.find_head(&mut head) else {
+                break Err(PushError::Full(item));
+            };
+
+            if self.cell(head).cmpxchg(null(), count, item, count).is_ok() {
+                self.publish_head(head);
+                break Ok(());
+            }
+            backoff.wait();
+        };
+        self.leave();
+        pushed
+    }
 
-                if !comp(
-                    prev_idx,
-                    prev_count,
-                    head,
-                    current_count,
-                    PtrType::<T>::MAX_W,
-                ) {
-                    if prev_ptr.is_null() && current_ptr.is_null() {
-                        // empty list
-                        break (prev_count, prev_ptr);
+    /// Pushes an item into the queue, displacing the oldest item if it is full.
+    ///
+    /// A full queue is detected at its oldest cell, which is then swapped from (old, count) to (item, count + 1) with a single CAS.
+    /// This is the combined effect of a pop and a push on that cell, so the displaced item is removed exactly once.
+    /// Returns the displaced item, or the item as an error if the queue is closed.
+    pub(crate) fn force_push(&self, item: *const T) -> Result<Option<*const T>, *const T> {
+        if !self.enter() {
+            return Err(item);
+        }
+        let mut backoff = W::default();
+        let mut head = self.head.load(Ordering::Acquire);
+        let displaced = loop {
+            match self.find_head(&mut head) {
+                Ok(count) => {
+                    if self.cell(head).cmpxchg(null(), count, item, count).is_ok() {
+                        self.publish_head(head);
+                        break None;
                     }
-                    if !prev_ptr.is_null() && !current_ptr.is_null() {
-                        // list full
-                        return Err(item);
+                }
+                Err((count, oldest)) => {
+                    let next_count = (count + 1) % Self::MAX_W;
+                    if self
+                        .cell(head)
+                        .cmpxchg(oldest, count, item, next_count)
+                        .is_ok()
+                    {
+                        // the cell is the newest one now, the oldest item follows it
+                        self.publish_tail(head);
+                        self.publish_head(head);
+                        break Some(oldest);
                     }
                 }
-                head = (head + 1) % self.buffer.len();
+            }
+            backoff.wait();
+        };
+        self.leave();
+        Ok(displaced)
+    }
+
+    /// Pushes the items returned by `next` into consecutive cells, until it returns None or the queue is full.
+    ///
+    /// Once an item has been pushed, the following cell is claimed directly with the count it is expected to have,
+    /// the buffer is only scanned again if that fails. The head hint is published once, after the last push.
+    /// Returns the number of pushed items, and the item which could not be pushed, if any.
+    pub(crate) fn push_batch(
+        &self,
+        next: impl FnMut() -> Option<*const T>,
+    ) -> (usize, Option<PushError<*const T>>) {
+        self.push_batch_with(next, |idx, count, item| {
+            self.cell(idx)
+                .cmpxchg(null(), count, item, count)
+                .map(drop)
+                .map_err(|_| item)
+        })
+    }
+
+    /// Drives a batch push, `claim` attempts to move an item into the free cell `idx` with the given count.
+    fn push_batch_with<I>(
+        &self,
+        mut next: impl FnMut() -> Option<I>,
+        mut claim: impl FnMut(usize, u64, I) -> Result<(), I>,
+    ) -> (usize, Option<PushError<I>>) {
+        if !self.enter() {
+            return (0, next().map(PushError::Closed));
+        }
+        let mut backoff = W::default();
+        let mut pushed = 0;
+        let mut head = self.head.load(Ordering::Acquire);
+        // the count the cell at `head` is expected to be claimed with, if it directly follows a pushed item
+        let mut expected = None;
+        let rejected = 'batch: loop {
+            let Some(mut item) = next() else {
+                break None;
+            };
+            let count = loop {
+                let count = match expected.take() {
+                    Some(count) => count,
+                    None => match self.find_head(&mut head) {
+                        Ok(count) => count,
+                        Err(_) => break 'batch Some(PushError::Full(item)),
+                    },
+                };
+                match claim(head, count, item) {
+                    Ok(()) => break count,
+                    Err(it) => item = it,
+                }
+                backoff.wait();
+            };
+            pushed += 1;
+            head = self.buffer.next(head);
+            expected = Some(self.following_count(head, count));
+        };
+        if pushed > 0 {
+            self.head.store(head, Ordering::Release);
+        }
+        self.leave();
+        (pushed, rejected)
+    }
+
+    /// Pops up to `max` items from consecutive cells and passes them to `sink`.
+    ///
+    /// Once an item has been popped, the following cell is taken directly if it holds the next item of the same round,
+    /// the buffer is only scanned again if that fails. The tail hint is published once, after the last pop.
+    /// Returns the number of popped items.
+    pub(crate) fn pop_batch(&self, max: usize, sink: impl FnMut(*const T)) -> usize {
+        self.pop_batch_with(
+            max,
+            |idx, count, item| {
+                let next_count = (count + 1) % Self::MAX_W;
+                self.cell(idx)
+                    .cmpxchg(item, count, null(), next_count)
+                    .ok()
+                    .map(|_| item)
+            },
+            sink,
+        )
+    }
+
+    /// Drives a batch pop, `take` attempts to move the item out of cell `idx`, which holds the given (count, ptr).
+    fn pop_batch_with<R>(
+        &self,
+        max: usize,
+        mut take: impl FnMut(usize, u64, *const T) -> Option<R>,
+        mut sink: impl FnMut(R),
+    ) -> usize {
+        let mut backoff = W::default();
+        let mut popped = 0;
+        let mut tail = 0;
+        // the (index, count, ptr) of the cell following a popped one, if it holds the next item
+        let mut following = None;
+        while popped < max {
+            let Some((idx, count, item)) = following.take().or_else(|| self.find_tail()) else {
+                break;
             };
+            let Some(item) = take(idx, count, item) else {
+                backoff.wait();
+                continue;
+            };
+            sink(item);
+            popped += 1;
+            tail = self.buffer.next(idx);
 
-            let mut new_counter = count;
-            if prev_ptr.is_null() {
-                // empty list
-                new_counter = (count + PtrType::<T>::MAX_W - 1) % PtrType::<T>::MAX_W;
+            let expected = self.following_count(tail, count);
+            let (next_count, next) = self.cell(tail).components();
+            if next_count == expected && !next.is_null() {
+                following = Some((tail, next_count, next));
             }
+        }
+        if popped > 0 {
+            self.tail.store(tail, Ordering::Release);
+        }
+        popped
+    }
 
-            if head == 0 {
-                // wrap around
-                new_counter = (new_counter + 1) % PtrType::<T>::MAX_W;
+    /// The count an item pushed into cell `idx` has, if the previous cell holds an item with `count`.
+    #[inline]
+    fn following_count(&self, idx: usize, count: u64) -> u64 {
+        if idx == 0 {
+            // wrap around
+            (count + 1) % Self::MAX_W
+        } else {
+            count
+        }
+    }
+
+    /// Pushes an item as the only producer of the queue.
+    ///
+    /// Consumers only ever write occupied cells, so the empty cell at `cursor` can only change through this producer.
+    /// It is checked with a plain load and claimed with a plain store, instead of a CAS,
+    /// and the buffer is only scanned if the cursor is unknown.
+    /// Concurrent calls, or a concurrent `force_push`, corrupt the queue.
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn push_single(
+        &self,
+        cursor: &mut Cursor,
+        item: *const T,
+    ) -> Result<(), PushError<*const T>> {
+        if !self.enter() {
+            return Err(PushError::Closed(item));
+        }
+        let pushed = loop {
+            let (idx, count) = match *cursor {
+                Some(position) => position,
+                None => {
+                    let mut head = self.head.load(Ordering::Acquire);
+                    match self.find_head(&mut head) {
+                        Ok(count) => (head, count),
+                        Err(_) => break Err(PushError::Full(item)),
+                    }
+                }
+            };
+            let (current_count, current) = self.cell(idx).components();
+            if !current.is_null() {
+                // the oldest item has not been popped yet
+                break Err(PushError::Full(item));
+            }
+            if current_count != count {
+                // the cursor is outdated, e.g. by pushes before the queue was split
+                *cursor = None;
+                continue;
+            }
+            self.cell(idx).store(item, count);
+            let next = self.buffer.next(idx);
+            *cursor = Some((next, self.following_count(next, count)));
+            self.publish_head(idx);
+            break Ok(());
+        };
+        self.leave();
+        pushed
+    }
+
+    /// Pops an item as the only consumer of the queue.
+    ///
+    /// Producers only ever write empty cells, so the occupied cell at `cursor` can only change through this consumer.
+    /// It is emptied with a plain store instead of a CAS, and the buffer is only scanned if the cursor is unknown.
+    /// Concurrent calls, or a concurrent `force_push`, corrupt the queue.
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn pop_single(&self, cursor: &mut Cursor) -> Option<*const T> {
+        loop {
+            let (idx, count) = match *cursor {
+                Some(position) => position,
+                None => {
+                    let (tail, count, _) = self.find_tail()?;
+                    (tail, count)
+                }
+            };
+            let (current_count, item) = self.cell(idx).components();
+            if item.is_null() {
+                // the next item has not been pushed yet
+                return None;
             }
+            if current_count != count {
+                *cursor = None;
+                continue;
+            }
+            self.cell(idx).store(null(), (count + 1) % Self::MAX_W);
+            let next = self.buffer.next(idx);
+            *cursor = Some((next, self.following_count(next, count)));
+            self.publish_tail(idx);
+            return Some(item);
+        }
+    }
 
-            if self
-                .buffer
-                .inner()
-                .get(head)
-                .ok_or(item)?
-                .cmpxchg(null(), new_counter, item, new_counter)
-                .is_ok()
-            {
-                self.head
-                    .store((head + 1) % self.buffer.len(), Ordering::Release);
-                return Ok(());
+    /// Like `try_pop`, but pops as the only consumer, see `pop_single`.
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn try_pop_single(&self, cursor: &mut Cursor) -> Result<*const T, PopError> {
+        self.try_pop_with(|| self.pop_single(cursor))
+    }
+
+    /// Attempts to pop an item, telling an empty queue apart from a closed and drained one.
+    pub(crate) fn try_pop(&self) -> Result<*const T, PopError> {
+        self.try_pop_with(|| self.pop())
+    }
+
+    /// Calls `pop` and reports `PopError::Closed`, if it fails on a closed queue without pending pushes.
+    fn try_pop_with<R>(&self, mut pop: impl FnMut() -> Option<R>) -> Result<R, PopError> {
+        if let Some(item) = pop() {
+            return Ok(item);
+        }
+        let producers = self.producers.load(Ordering::Acquire);
+        if producers & CLOSED == 0 {
+            return Err(PopError::Empty);
+        }
+        // pushes may have completed after the first attempt
+        if let Some(item) = pop() {
+            return Ok(item);
+        }
+        if producers == CLOSED {
+            // no push was pending once the queue was closed
+            Err(PopError::Closed)
+        } else {
+            Err(PopError::Empty)
+        }
+    }
+
+    /// Registers a producer, returns false if the queue is closed.
+    fn enter(&self) -> bool {
+        if self.producers.fetch_add(1, Ordering::AcqRel) & CLOSED != 0 {
+            self.leave();
+            return false;
+        }
+        true
+    }
+
+    fn leave(&self) {
+        self.producers.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Closes the queue, returns false if it has already been closed.
+    ///
+    /// Pushes fail afterwards, while the remaining items can still be popped.
+    pub(crate) fn close(&self) -> bool {
+        self.producers.fetch_or(CLOSED, Ordering::AcqRel) & CLOSED == 0
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.producers.load(Ordering::Acquire) & CLOSED != 0
+    }
+
+    /// Indicates whether a producer may still push into the closed queue.
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn has_pending_pushes(&self) -> bool {
+        self.producers.load(Ordering::Acquire) != CLOSED
+    }
+
+    /// Scans the buffer, starting at the tail hint, for the oldest cell of the queue.
+    ///
+    /// Returns the index and the (count, ptr) components of that cell, or None if the queue is empty.
+    fn find_tail(&self) -> Option<(usize, u64, *const T)> {
+        let mut tail = self.tail.load(Ordering::Acquire);
+        let mut prev_idx = self.buffer.prev(tail);
+        let prev_item = self.cell(prev_idx);
+        let mut current_item = self.cell(tail);
+        let (mut prev_count, mut prev_ptr) = prev_item.components();
+        let (mut current_count, mut current_ptr) = current_item.components();
+
+        while comp(prev_idx, prev_count, tail, current_count, Self::MAX_W) {
+            tail = self.buffer.next(tail);
+            prev_idx = self.buffer.prev(tail);
+            current_item = self.cell(tail);
+            (prev_count, prev_ptr, (current_count, current_ptr)) =
+                (current_count, current_ptr, current_item.components());
+        }
+
+        if prev_ptr.is_null() && current_ptr.is_null() {
+            // empty queue
+            return None;
+        }
+
+        Some((tail, current_count, current_ptr))
+    }
+
+    /// Scans the buffer, starting at `head`, for the next free cell of the queue.
+    ///
+    /// On success `head` holds the index of the free cell and the count it has to be claimed with is returned.
+    /// If the queue is full, `head` holds the index of the oldest cell and its (count, ptr) components are returned as an error.
+    fn find_head(&self, head: &mut usize) -> Result<u64, (u64, *const T)> {
+        let (count, prev_ptr) = loop {
+            let prev_idx = self.buffer.prev(*head);
+            let current_item = self.cell(*head);
+            let prev_item = self.cell(prev_idx);
+            let (prev_count, prev_ptr) = prev_item.components();
+            let (current_count, current_ptr) = current_item.components();
+
+            if !prev_ptr.is_null() && current_ptr.is_null() {
+                break (prev_count, prev_ptr);
+            }
+
+            if !comp(prev_idx, prev_count, *head, current_count, Self::MAX_W) {
+                if prev_ptr.is_null() && current_ptr.is_null() {
+                    // empty list
+                    break (prev_count, prev_ptr);
+                }
+                if !prev_ptr.is_null() && !current_ptr.is_null() {
+                    // list full
+                    return Err((current_count, current_ptr));
+                }
             }
+            *head = self.buffer.next(*head);
+        };
+
+        let mut new_counter = count;
+        if prev_ptr.is_null() {
+            // empty list
+            new_counter = (count + Self::MAX_W - 1) % Self::MAX_W;
+        }
+
+        if *head == 0 {
+            // wrap around
+            new_counter = (new_counter + 1) % Self::MAX_W;
         }
+
+        Ok(new_counter)
+    }
+
+    #[inline]
+    fn cell(&self, idx: usize) -> &components::Item<T, B::Storage> {
+        self.buffer.cell(idx)
+    }
+
+    /// Moves the head hint past the cell at `idx`
+    #[inline]
+    fn publish_head(&self, idx: usize) {
+        self.head.store(self.buffer.next(idx), Ordering::Release);
+    }
+
+    /// Moves the tail hint past the cell at `idx`
+    #[inline]
+    fn publish_tail(&self, idx: usize) {
+        self.tail.store(self.buffer.next(idx), Ordering::Release);
     }
 
     /// Returns the total capacity of the underlying buffer.
@@ -174,12 +638,7 @@ impl<T, B: components::Buffer<T>> ArrayQueue<T, B> {
             }
         } else {
             // may be full or empty
-            let (_, item) = self
-                .buffer
-                .inner()
-                .get(head)
-                .expect("head outside of cap")
-                .components();
+            let (_, item) = self.cell(head).components();
             if item.is_null() {
                 // empty
                 0
@@ -206,215 +665,1789 @@ impl<T, B: components::Buffer<T>> ArrayQueue<T, B> {
 #[cfg(feature = "alloc")]
 mod heap_based {
     use super::*;
-    use alloc::boxed::Box;
+    use crate::hazard::{Hazard, Hazards};
+    use crate::pool::BoxPool;
+    use alloc::{
+        alloc::{Allocator, Global},
+        boxed::Box,
+        vec::Vec,
+    };
 
-    pub struct HeapBackedQueue<T>(ArrayQueue<T, components::FixedBuf<T>>);
+    /// A bounded, heap-allocated queue.
+    ///
+    /// Items are boxed on push. The boxes are drawn from a per-queue pool,
+    /// which holds `size` preallocated boxes and retains up to `2 * size` boxes,
+    /// so a steady push/pop loop does not allocate.
+    ///
+    /// The buffer, the pool and all item boxes are allocated in `A`.
+    /// The cells of the buffer are laid out according to `L`, see `CellLayout`,
+    /// and encoded according to `S`, see `Storage`.
+    pub struct HeapBackedQueue<
+        T,
+        A: Allocator = Global,
+        L: CellLayout = Dense,
+        B: Backoff = Spin,
+        S: Storage = DefaultStorage,
+    >(
+        ArrayQueue<T, components::FixedBuf<T, A, L, S>, B>,
+        BoxPool<T, A>,
+        /// Announces the items, which are referenced in place by `peek_with` and the like
+        Hazards<A>,
+    );
 
     impl<T> HeapBackedQueue<T> {
         pub fn new(size: usize) -> Self {
-            assert!(size > 0, "Size of the queue must be greater than 0");
-            Self(ArrayQueue::new_in(components::FixedBuf::new(size)))
+            Self::new_in(size, Global)
         }
 
-        /// Attempts to push an item into the queue.
-        /// Returns the item as an error if the queue is full.
+        /// Creates a queue, whose capacity is `size` rounded up to the next power of two.
+        ///
+        /// Any queue with a power of two capacity wraps its indices with a mask instead of a division.
+        /// Use `new` if the capacity has to be exactly `size`.
         ///
         /// # Examples
         ///
         /// ```
         /// use nblfq::HeapBackedQueue;
         ///
-        /// let q = HeapBackedQueue::new(2);
+        /// let q = HeapBackedQueue::<i32>::new_pow2(5);
         ///
-        /// assert_eq!(q.push(10), Ok(()));
-        /// assert_eq!(q.push(20), Ok(()));
-        /// assert_eq!(q.push(30), Err(30));
-        /// assert_eq!(q.pop(), Some(10));
+        /// assert_eq!(q.capacity(), 8);
         /// ```
-        pub fn push(&self, item: T) -> Result<(), T> {
-            let item = Box::into_raw(Box::new(item));
-            self.0
-                .push(item)
-                .map_err(|item| unsafe { *Box::from_raw(item as *mut T) })
+        pub fn new_pow2(size: usize) -> Self {
+            Self::new(size.next_power_of_two())
         }
+    }
 
-        /// Pushes an item into the queue, overwriting the last item if it is full
-        /// This method does NOT guarantee atomicity. It simply calls pop(), until push() is succesfull.
-        /// This also means that this method may spin for some time.
-        /// The last popped item is returned, if the queue was full
+    impl<T, A: Allocator + Clone> HeapBackedQueue<T, A> {
+        /// Creates a queue of capacity `size`, which allocates all of its memory in `alloc`.
         ///
         /// # Examples
         ///
         /// ```
+        /// #![feature(allocator_api)]
         /// use nblfq::HeapBackedQueue;
+        /// use std::alloc::Global;
         ///
-        /// let q = HeapBackedQueue::new(2);
+        /// let q = HeapBackedQueue::new_in(2, Global);
         ///
-        /// assert_eq!(q.force_push(10), None);
-        /// assert_eq!(q.force_push(20), None);
-        /// assert_eq!(q.force_push(30), Some(10));
-        /// assert_eq!(q.pop(), Some(20));
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.pop(), Some(10));
         /// ```
-        pub fn force_push(&self, item: T) -> Option<T> {
-            let mut popped_item = None;
-            let mut container = item;
-            let mut backoff = 1;
-            while let Err(item) = self.push(container) {
-                container = item;
-                for _ in 0..backoff {
-                    use core::hint::spin_loop;
-
-                    spin_loop();
-                }
-                backoff = (backoff * 2).min(1024);
-                popped_item = self.pop();
-            }
-            popped_item
+        pub fn new_in(size: usize, alloc: A) -> Self {
+            Self::with_layout_in(size, alloc)
         }
+    }
 
-        /// pop the last item, if an item is contained
+    impl<T, L: CellLayout, B: Backoff, S: Storage> HeapBackedQueue<T, Global, L, B, S> {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L`
+        /// and encoded according to `S`, and which waits with `B` between retries.
         ///
         /// # Examples
         ///
         /// ```
-        /// use nblfq::HeapBackedQueue;
+        /// use nblfq::{HeapBackedQueue, Padded};
+        ///
+        /// let q = HeapBackedQueue::<i32, _, Padded>::with_layout(2);
         ///
-        /// let q = HeapBackedQueue::new(1);
         /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
         ///
+        /// ```
+        /// use nblfq::{DWord, Dense, HeapBackedQueue, backoff::Spin};
+        ///
+        /// let q = HeapBackedQueue::<i32, _, Dense, Spin, DWord>::with_layout(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
         /// assert_eq!(q.pop(), Some(10));
-        /// assert!(q.pop().is_none());
         /// ```
-        pub fn pop(&self) -> Option<T> {
-            self.0
-                .pop()
-                .map(|item| unsafe { *Box::from_raw(item as *mut T) })
-        }
-
-        /// Returns the total capacity of the underlying buffer.
-        pub fn capacity(&self) -> usize {
-            self.0.capacity()
-        }
-
-        /// Returns the current len of the queue.
-        /// This value may be stale.
-        pub fn len(&self) -> usize {
-            self.0.len()
-        }
-
-        /// Indicates whether the queue is empty.
-        /// The result may be stale.
-        pub fn is_empty(&self) -> bool {
-            self.0.is_empty()
+        pub fn with_layout(size: usize) -> Self {
+            Self::with_layout_in(size, Global)
         }
 
-        /// Indicates whether the queue is full.
-        /// The result may be stale.
-        pub fn is_full(&self) -> bool {
-            self.0.is_full()
-        }
-    }
-
-    impl<T> Debug for HeapBackedQueue<T> {
-        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-            f.pad("HeapBackedQueue { ... }")
-        }
-    }
-
-    impl<T> Drop for HeapBackedQueue<T> {
-        fn drop(&mut self) {
-            // drop all leaked boxes
-            while self.pop().is_some() {}
-        }
-    }
-
-    impl<T> IntoIterator for HeapBackedQueue<T> {
-        type Item = T;
-        type IntoIter = impl Iterator<Item = Self::Item>;
-
-        fn into_iter(self) -> Self::IntoIter {
-            iter::from_fn(move || self.pop())
-        }
-    }
-
-    /// Safety: HeapBackedQueue sends owned T's between threads.
-    /// It is only safe to do so, if T is Send
-    unsafe impl<T: Send> Sync for HeapBackedQueue<T> {}
-    unsafe impl<T: Send> Send for HeapBackedQueue<T> {}
-}
-
-mod heapless {
-    use super::*;
-
-    pub struct HeaplessQueue<const N: usize, T>(ArrayQueue<T, components::HeaplessBuf<N, T>>);
-
-    impl<const N: usize, T> HeaplessQueue<N, T> {
-        pub fn new() -> Self {
-            assert!(N > 0, "Size of the queue must be greater than 0");
-            Self(ArrayQueue::new_in(components::HeaplessBuf::new()))
-        }
-
-        /// Attempts to push an item into the queue.
-        /// Returns the item as an error if the queue is full.
+        /// Pushes an item, which is already boxed, without moving it into a box of the pool.
+        /// Returns the box as an error if the queue is full or closed.
+        ///
+        /// The box is returned to the pool, once its item is popped with `pop`.
         ///
         /// # Examples
         ///
         /// ```
-        /// use nblfq::HeaplessQueue;
+        /// use nblfq::{HeapBackedQueue, PushError};
         ///
-        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// let q = HeapBackedQueue::new(1);
         ///
-        /// assert_eq!(q.push(&10), Ok(()));
-        /// assert_eq!(q.push(&20), Ok(()));
-        /// assert_eq!(q.push(&30), Err(&30));
-        /// assert_eq!(q.pop(), Some(&10));
+        /// assert_eq!(q.push_box(Box::new(10)), Ok(()));
+        /// assert_eq!(q.push_box(Box::new(20)), Err(PushError::Full(Box::new(20))));
+        /// assert_eq!(q.pop(), Some(10));
         /// ```
-        pub fn push(&self, item: &'static T) -> Result<(), &'static T> {
-            let item = item as *const T;
-            self.0.push(item).map_err(|item| unsafe { &*item })
+        pub fn push_box(&self, item: Box<T>) -> Result<(), PushError<Box<T>>> {
+            self.0
+                .push(Box::into_raw(item))
+                .map_err(|err| err.map(|item| unsafe { Box::from_raw(item as *mut T) }))
         }
 
-        /// Pushes an item into the queue, overwriting the last item if it is full
-        /// This method does NOT guarantee atomicity. It simply calls pop(), until push() is succesfull.
-        /// This also means that this method may spin for some time.
-        /// The last popped item is returned, if the queue was full
+        /// Pops the last item together with its box, if an item is contained.
+        /// The box is taken out of the queue, it is not returned to the pool.
+        /// Like `pop`, this waits for reads of the item in place by other threads.
         ///
         /// # Examples
         ///
         /// ```
-        /// use nblfq::HeaplessQueue;
+        /// use nblfq::HeapBackedQueue;
         ///
-        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// let q = HeapBackedQueue::new(1);
+        /// q.push(10).unwrap();
         ///
-        /// assert_eq!(q.force_push(&10), None);
-        /// assert_eq!(q.force_push(&20), None);
-        /// assert_eq!(q.force_push(&30), Some(&10));
-        /// assert_eq!(q.pop(), Some(&20));
+        /// assert_eq!(q.pop_box(), Some(Box::new(10)));
         /// ```
-        pub fn force_push(&self, item: &'static T) -> Option<&'static T> {
-            let mut popped_item = None;
-            let mut backoff = 1;
-            while self.push(item).is_err() {
-                for _ in 0..backoff {
-                    use core::hint::spin_loop;
+        pub fn pop_box(&self) -> Option<Box<T>> {
+            // Safety: every item is boxed in Global, either by the pool or by the caller of push_box
+            self.0.pop().map(|item| unsafe {
+                self.2.wait_for::<B>(item as *const ());
+                Box::from_raw(item as *mut T)
+            })
+        }
+    }
 
-                    spin_loop();
-                }
-                backoff = (backoff * 2).min(1024);
-                popped_item = self.pop();
-            }
-            popped_item
+    impl<T, A: Allocator + Clone, L: CellLayout, B: Backoff, S: Storage>
+        HeapBackedQueue<T, A, L, B, S>
+    {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L` and encoded according to `S`,
+        /// which waits with `B` between retries and which allocates all of its memory in `alloc`.
+        pub fn with_layout_in(size: usize, alloc: A) -> Self {
+            assert!(size > 0, "Size of the queue must be greater than 0");
+            Self(
+                ArrayQueue::new_in(components::FixedBuf::new_in(size, alloc.clone())),
+                BoxPool::new_in(size, 2 * size, alloc.clone()),
+                Hazards::new_in(alloc),
+            )
         }
+    }
 
-        /// pop the last item, if an item is contained
+    impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> HeapBackedQueue<T, A, L, B, S> {
+        /// Attempts to push an item into the queue.
+        /// Returns the item as an error if the queue is full or closed.
         ///
         /// # Examples
         ///
         /// ```
-        /// use nblfq::HeaplessQueue;
+        /// use nblfq::{HeapBackedQueue, PushError};
         ///
-        /// let q: HeaplessQueue<1, _> = HeaplessQueue::new();
-        /// assert_eq!(q.push(&10), Ok(()));
+        /// let q = HeapBackedQueue::new(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.push(20), Ok(()));
+        /// assert_eq!(q.push(30), Err(PushError::Full(30)));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn push(&self, item: T) -> Result<(), PushError<T>> {
+            let item = self.1.boxed(item);
+            self.0
+                .push(item)
+                .map_err(|err| err.map(|item| unsafe { self.1.unboxed(item) }))
+        }
+
+        /// Pushes an item into the queue, overwriting the oldest item if it is full
+        /// The oldest item is displaced atomically, with the same kind of cell CAS as push() and pop().
+        /// Concurrent calls therefore evict one item each, and the evicted item is returned
+        /// Returns the item as an error if the queue is closed
+        /// Like `pop`, this waits for reads of the evicted item in place by other threads.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        ///
+        /// assert_eq!(q.force_push(10), Ok(None));
+        /// assert_eq!(q.force_push(20), Ok(None));
+        /// assert_eq!(q.force_push(30), Ok(Some(10)));
+        /// assert_eq!(q.pop(), Some(20));
+        /// ```
+        pub fn force_push(&self, item: T) -> Result<Option<T>, T> {
+            let item = self.1.boxed(item);
+            match self.0.force_push(item) {
+                Ok(displaced) => Ok(displaced.map(|item| unsafe { self.take(item) })),
+                Err(item) => Err(unsafe { self.1.unboxed(item) }),
+            }
+        }
+
+        /// pop the last item, if an item is contained
+        ///
+        /// If another thread is reading the popped item in place, through `peek_with`, `pop_if`, `snapshot` or `debug_items`,
+        /// this waits until that read is done, before the item is moved out of its box.
+        /// While no such read is in progress, a pop does not wait.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(1);
+        /// assert_eq!(q.push(10), Ok(()));
+        ///
+        /// assert_eq!(q.pop(), Some(10));
+        /// assert!(q.pop().is_none());
+        /// ```
+        pub fn pop(&self) -> Option<T> {
+            self.0.pop().map(|item| unsafe { self.take(item) })
+        }
+
+        /// Pushes items from `items`, until it is exhausted or the queue is full or closed.
+        /// The items are pushed into consecutive cells with a single scan, and the head is published once.
+        /// Returns the number of pushed items and the item which could not be pushed, if any.
+        /// The remaining items are left in `items`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeapBackedQueue, PushError};
+        ///
+        /// let q = HeapBackedQueue::new(3);
+        /// let mut items = 1..=5;
+        ///
+        /// assert_eq!(q.push_iter(&mut items), (3, Some(PushError::Full(4))));
+        /// assert_eq!(items.next(), Some(5));
+        /// assert_eq!(q.pop_n(5), [1, 2, 3]);
+        /// ```
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = T>,
+        ) -> (usize, Option<PushError<T>>) {
+            let (pushed, rejected) = self
+                .0
+                .push_batch(|| items.next().map(|item| self.1.boxed(item)));
+            let rejected = rejected.map(|err| err.map(|item| unsafe { self.1.unboxed(item) }));
+            (pushed, rejected)
+        }
+
+        /// Pushes clones of `items`, until the queue is full or closed.
+        /// Returns the number of pushed items, the remaining ones are `&items[n..]`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        ///
+        /// assert_eq!(q.push_slice(&[1, 2, 3]), 2);
+        /// assert_eq!(q.pop(), Some(1));
+        /// ```
+        pub fn push_slice(&self, items: &[T]) -> usize
+        where
+            T: Clone,
+        {
+            self.push_iter(&mut items.iter().cloned()).0
+        }
+
+        /// Pops up to `max` items into `out`.
+        /// The items are popped from consecutive cells with a single scan, and the tail is published once.
+        /// Returns the number of popped items.
+        /// Like `pop`, this waits for reads of the popped items in place by other threads.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(4);
+        /// q.push_slice(&[1, 2, 3]);
+        ///
+        /// let mut out = Vec::new();
+        /// assert_eq!(q.pop_into(&mut out, 2), 2);
+        /// assert_eq!(out, [1, 2]);
+        /// ```
+        pub fn pop_into(&self, out: &mut impl Extend<T>, max: usize) -> usize {
+            self.0.pop_batch(max, |item| {
+                out.extend(Some(unsafe { self.take(item) }));
+            })
+        }
+
+        /// Pops up to `n` items.
+        pub fn pop_n(&self, n: usize) -> Vec<T> {
+            let mut items = Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop an item.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        /// Like `pop`, it waits for reads of the popped item in place by other threads.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeapBackedQueue, PopError};
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// assert_eq!(q.try_pop(), Err(PopError::Empty));
+        ///
+        /// q.push(10).unwrap();
+        /// q.close();
+        /// assert_eq!(q.try_pop(), Ok(10));
+        /// assert_eq!(q.try_pop(), Err(PopError::Closed));
+        /// ```
+        pub fn try_pop(&self) -> Result<T, PopError> {
+            self.0.try_pop().map(|item| unsafe { self.take(item) })
+        }
+
+        /// Pushes an item as the only producer, see `ArrayQueue::push_single`.
+        pub(crate) fn push_single(&self, cursor: &mut Cursor, item: T) -> Result<(), PushError<T>> {
+            let item = self.1.boxed(item);
+            self.0
+                .push_single(cursor, item)
+                .map_err(|err| err.map(|item| unsafe { self.1.unboxed(item) }))
+        }
+
+        /// Pops an item as the only consumer, see `ArrayQueue::pop_single`.
+        pub(crate) fn pop_single(&self, cursor: &mut Cursor) -> Option<T> {
+            self.0
+                .pop_single(cursor)
+                .map(|item| unsafe { self.take(item) })
+        }
+
+        /// Attempts to pop an item as the only consumer, see `ArrayQueue::pop_single`.
+        pub(crate) fn try_pop_single(&self, cursor: &mut Cursor) -> Result<T, PopError> {
+            self.0
+                .try_pop_single(cursor)
+                .map(|item| unsafe { self.take(item) })
+        }
+
+        /// Calls `f` with the oldest item without popping it, returns None if the queue is empty.
+        ///
+        /// The item stays in the queue and can still be popped by another consumer,
+        /// but that pop waits until `f` has returned, before it moves the item out of its box.
+        /// Pops of other items do not wait. `f` should therefore be short, and it must not pop from the queue itself,
+        /// since it would wait for its own read of the item.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// assert_eq!(q.peek_with(|item| *item), None);
+        ///
+        /// q.push(10).unwrap();
+        /// q.push(20).unwrap();
+        /// assert_eq!(q.peek_with(|item| *item), Some(10));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R>
+        where
+            T: Sync,
+        {
+            let hazard = self.2.claim();
+            loop {
+                let (idx, count, item) = self.0.peek()?;
+                if self.protect(&hazard, idx, count, item) {
+                    // Safety: the item is not moved out of its box, until the hazard is dropped
+                    return Some(f(unsafe { &*item }));
+                }
+            }
+        }
+
+        /// Pops the oldest item, if `pred` returns true for it.
+        /// Returns None if the queue is empty or `pred` returns false.
+        ///
+        /// The item is popped with a CAS against the round count of its cell, as observed before calling `pred`,
+        /// so another consumer cannot take it in between. If it does take it first, `pred` is called for the next item.
+        /// `pred` reads the item in place like the closure of `peek_with`, so it must not pop from the queue itself,
+        /// since it would wait for its own read of the item.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// q.push(10).unwrap();
+        ///
+        /// assert_eq!(q.pop_if(|item| *item > 10), None);
+        /// assert_eq!(q.pop_if(|item| *item == 10), Some(10));
+        /// ```
+        pub fn pop_if(&self, mut pred: impl FnMut(&T) -> bool) -> Option<T>
+        where
+            T: Sync,
+        {
+            let hazard = self.2.claim();
+            let item = self.0.pop_if(|idx, count, item| {
+                if !self.protect(&hazard, idx, count, item) {
+                    // an item popped meanwhile fails the CAS, so the next one is checked
+                    return true;
+                }
+                // Safety: the item is not moved out of its box, until the hazard is cleared
+                let pop = pred(unsafe { &*item });
+                hazard.clear();
+                pop
+            })?;
+            Some(unsafe { self.take(item) })
+        }
+
+        /// Pops and drops the items, which are in the queue when it is called.
+        ///
+        /// Items pushed concurrently are left in the queue, so this terminates under a steady stream of pushes,
+        /// unlike `while q.pop().is_some() {}`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// q.push(10).unwrap();
+        /// q.push(20).unwrap();
+        ///
+        /// q.clear();
+        /// assert!(q.is_empty());
+        /// ```
+        pub fn clear(&self) {
+            self.drain().for_each(drop);
+        }
+
+        /// Returns an iterator, which pops the items that are in the queue when it is called.
+        ///
+        /// Unlike `into_iter`, the queue is not consumed. Items pushed after the call are not yielded,
+        /// and items the iterator has not reached when it is dropped remain in the queue.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(3);
+        /// q.push(10).unwrap();
+        /// q.push(20).unwrap();
+        ///
+        /// let mut drain = q.drain();
+        /// q.push(30).unwrap();
+        /// assert_eq!(drain.next(), Some(10));
+        /// assert_eq!(drain.next(), Some(20));
+        /// assert_eq!(drain.next(), None);
+        ///
+        /// assert_eq!(q.pop(), Some(30));
+        /// ```
+        pub fn drain(&self) -> impl Iterator<Item = T> {
+            let end = self.0.end();
+            iter::from_fn(move || {
+                self.0
+                    .pop_before(end)
+                    .map(|item| unsafe { self.take(item) })
+            })
+        }
+
+        /// Removes the items in the queue, for which `f` returns false, and re-enqueues the others.
+        ///
+        /// The items, which are in the queue when it is called, are drained and the kept ones are pushed again,
+        /// so they are queued behind items pushed concurrently.
+        /// If a kept item cannot be pushed, since concurrent pushes have filled the queue or it has been closed,
+        /// it is returned as an error and the items following it are left in the queue.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(4);
+        /// for i in 0..4 {
+        ///     q.push(i).unwrap();
+        /// }
+        ///
+        /// assert_eq!(q.retain(|item| item % 2 == 0), Ok(()));
+        /// assert_eq!(q.pop_n(4), [0, 2]);
+        /// ```
+        pub fn retain(&self, mut f: impl FnMut(&T) -> bool) -> Result<(), PushError<T>> {
+            for item in self.drain() {
+                if f(&item) {
+                    self.push(item)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Returns clones of the items in the queue, from the oldest to the newest.
+        ///
+        /// The cells are walked from the tail towards the head, and each item is cloned in place like in `peek_with`,
+        /// so only a pop of the item, which is being cloned, waits for the clone. Items popped or pushed during the walk may be missing or included,
+        /// the result is a best-effort snapshot, which does not modify the queue.
+        /// The `Clone` impl of `T` must not pop from the queue, since it would wait for its own read of the item.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(3);
+        /// q.push(10).unwrap();
+        /// q.push(20).unwrap();
+        ///
+        /// assert_eq!(q.snapshot(), [10, 20]);
+        /// assert_eq!(q.len(), 2);
+        /// ```
+        pub fn snapshot(&self) -> Vec<T>
+        where
+            T: Clone + Sync,
+        {
+            let mut items = Vec::new();
+            self.for_each_item(|item| items.push(item.clone()));
+            items
+        }
+
+        /// Returns a value, whose `Debug` output lists a best-effort snapshot of the items along with the state of the ring.
+        ///
+        /// The `Debug` output of the queue itself only shows the capacity, the head and tail hints and the round count of every cell.
+        /// The items are formatted in place like in `peek_with`, so only a pop of the item, which is being formatted, waits for it.
+        /// The `Debug` impl of `T` must not pop from the queue, since it would wait for its own read of the item.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeapBackedQueue;
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// q.push(10).unwrap();
+        ///
+        /// assert_eq!(
+        ///     format!("{:?}", q.debug_items()),
+        ///     "HeapBackedQueue { items: [10], capacity: 2, head: 1, tail: 0, counts: [0, 0] }"
+        /// );
+        /// ```
+        pub fn debug_items(&self) -> impl Debug + '_
+        where
+            T: Debug + Sync,
+        {
+            core::fmt::from_fn(move |f| {
+                let items = core::fmt::from_fn(|f| {
+                    let mut list = f.debug_list();
+                    self.for_each_item(|item| {
+                        list.entry(item);
+                    });
+                    list.finish()
+                });
+                self.0.fmt_as("HeapBackedQueue", Some(&items), f)
+            })
+        }
+
+        /// Calls `f` with each item of the queue in place, skipping the ones popped in the meantime.
+        fn for_each_item(&self, mut f: impl FnMut(&T)) {
+            let hazard = self.2.claim();
+            for (idx, count, item) in self.0.items() {
+                if self.protect(&hazard, idx, count, item) {
+                    // Safety: the item is not moved out of its box, until the hazard is cleared
+                    f(unsafe { &*item });
+                    hazard.clear();
+                }
+            }
+        }
+
+        /// Announces `item`, which `peek` returned for cell `idx`, through `hazard`.
+        /// Returns false if it has been popped in the meantime.
+        fn protect(&self, hazard: &Hazard<'_>, idx: usize, count: u64, item: *const T) -> bool {
+            hazard.protect(item as *const (), || self.0.holds(idx, count, item))
+        }
+
+        /// Moves a popped item out of its box.
+        ///
+        /// # Safety
+        ///
+        /// `item` must have been popped from this queue.
+        unsafe fn take(&self, item: *const T) -> T {
+            self.2.wait_for::<B>(item as *const ());
+            unsafe { self.1.unboxed(item) }
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining items can still be popped.
+        /// Returns false if the queue has already been closed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeapBackedQueue, PushError};
+        ///
+        /// let q = HeapBackedQueue::new(2);
+        /// q.push(10).unwrap();
+        ///
+        /// assert!(q.close());
+        /// assert!(!q.close());
+        /// assert_eq!(q.push(20), Err(PushError::Closed(20)));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    /// Shows the state of the ring without the items, see `HeapBackedQueue::debug_items`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::HeapBackedQueue;
+    ///
+    /// let q = HeapBackedQueue::new(2);
+    /// q.push(10).unwrap();
+    ///
+    /// assert_eq!(
+    ///     format!("{q:?}"),
+    ///     "HeapBackedQueue { capacity: 2, head: 1, tail: 0, counts: [0, 0] }"
+    /// );
+    /// ```
+    impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Debug
+        for HeapBackedQueue<T, A, L, B, S>
+    {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            self.0.fmt_as("HeapBackedQueue", None, f)
+        }
+    }
+
+    impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Drop
+        for HeapBackedQueue<T, A, L, B, S>
+    {
+        fn drop(&mut self) {
+            // drop all remaining items, the pool frees their boxes
+            while self.pop().is_some() {}
+        }
+    }
+
+    impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> IntoIterator
+        for HeapBackedQueue<T, A, L, B, S>
+    {
+        type Item = T;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: HeapBackedQueue sends owned T's between threads.
+    /// It is only safe to do so, if T is Send.
+    /// Items are allocated and freed through a shared &A, so A must be Sync as well
+    unsafe impl<T: Send, A: Allocator + Sync, L: CellLayout, B: Backoff, S: Storage> Sync
+        for HeapBackedQueue<T, A, L, B, S>
+    {
+    }
+    unsafe impl<T: Send, A: Allocator + Send, L: CellLayout, B: Backoff, S: Storage> Send
+        for HeapBackedQueue<T, A, L, B, S>
+    {
+    }
+}
+
+#[cfg(feature = "alloc")]
+mod inline {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::{mem::align_of, ptr::without_provenance};
+
+    // The ptr half of each Item<T> only encodes the state of its slot.
+    // The sentinels are multiples of T's alignment, so they never collide with null.
+
+    /// The slot has been claimed by a producer, which is writing its value.
+    fn writing<T>() -> *const T {
+        without_provenance(align_of::<T>())
+    }
+
+    /// The slot holds a value.
+    fn full<T>() -> *const T {
+        without_provenance(2 * align_of::<T>())
+    }
+
+    /// The slot has been claimed by a consumer, which is reading its value.
+    fn reading<T>() -> *const T {
+        without_provenance(3 * align_of::<T>())
+    }
+
+    /// A bounded, heap-allocated queue, which stores its items in place.
+    ///
+    /// Unlike `HeapBackedQueue`, pushing and popping do not allocate.
+    /// A slot is claimed with the same cell CAS as in `HeapBackedQueue`, its value is copied afterwards.
+    /// A consumer which reaches a slot, whose value is still being copied, waits for the copy to finish.
+    ///
+    /// The queue is therefore blocking, not lock-free: a producer or consumer preempted in the middle of a copy
+    /// stalls every consumer, as well as `force_push` on a full queue, until it resumes.
+    /// The cells holding the slot states are laid out according to `L`, see `CellLayout`.
+    pub struct InlineQueue<T, L: CellLayout = Dense, B: Backoff = Spin>(
+        ArrayQueue<T, components::InlineBuf<T, L>, B>,
+    );
+
+    impl<T> InlineQueue<T> {
+        pub fn new(size: usize) -> Self {
+            Self::with_layout(size)
+        }
+
+        /// Creates a queue, whose capacity is `size` rounded up to the next power of two.
+        ///
+        /// Any queue with a power of two capacity wraps its indices with a mask instead of a division.
+        /// Use `new` if the capacity has to be exactly `size`.
+        pub fn new_pow2(size: usize) -> Self {
+            Self::new(size.next_power_of_two())
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff> InlineQueue<T, L, B> {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L`
+        /// and which waits with `B` between retries.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{InlineQueue, Padded};
+        ///
+        /// let q: InlineQueue<i32, Padded> = InlineQueue::with_layout(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn with_layout(size: usize) -> Self {
+            assert!(size > 0, "Size of the queue must be greater than 0");
+            Self(ArrayQueue::new_in(components::InlineBuf::new(size)))
+        }
+
+        /// Attempts to push an item into the queue.
+        /// Returns the item as an error if the queue is full or closed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{InlineQueue, PushError};
+        ///
+        /// let q = InlineQueue::new(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.push(20), Ok(()));
+        /// assert_eq!(q.push(30), Err(PushError::Full(30)));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn push(&self, item: T) -> Result<(), PushError<T>> {
+            let q = &self.0;
+            if !q.enter() {
+                return Err(PushError::Closed(item));
+            }
+            let mut backoff = B::default();
+            let mut head = q.head.load(Ordering::Acquire);
+            let pushed = loop {
+                let Ok(count) = q.find_head(&mut head) else {
+                    break Err(PushError::Full(item));
+                };
+
+                if q.cell(head)
+                    .cmpxchg(null(), count, writing(), count)
+                    .is_ok()
+                {
+                    // Safety: the slot has been claimed by this thread
+                    unsafe { q.buffer.write(head, item) };
+                    let published = q.cell(head).cmpxchg(writing(), count, full(), count);
+                    debug_assert!(published.is_ok(), "claimed slot changed while writing");
+                    q.publish_head(head);
+                    break Ok(());
+                }
+                backoff.wait();
+            };
+            q.leave();
+            pushed
+        }
+
+        /// Pushes an item into the queue, overwriting the oldest item if it is full
+        /// The oldest item is displaced atomically, with the same kind of cell CAS as push() and pop().
+        /// Concurrent calls therefore evict one item each, and the evicted item is returned
+        /// Returns the item as an error if the queue is closed
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::InlineQueue;
+        ///
+        /// let q = InlineQueue::new(2);
+        ///
+        /// assert_eq!(q.force_push(10), Ok(None));
+        /// assert_eq!(q.force_push(20), Ok(None));
+        /// assert_eq!(q.force_push(30), Ok(Some(10)));
+        /// assert_eq!(q.pop(), Some(20));
+        /// ```
+        pub fn force_push(&self, item: T) -> Result<Option<T>, T> {
+            let q = &self.0;
+            if !q.enter() {
+                return Err(item);
+            }
+            let mut backoff = B::default();
+            let mut head = q.head.load(Ordering::Acquire);
+            let displaced = loop {
+                match q.find_head(&mut head) {
+                    Ok(count) => {
+                        if q.cell(head)
+                            .cmpxchg(null(), count, writing(), count)
+                            .is_ok()
+                        {
+                            // Safety: the slot has been claimed by this thread
+                            unsafe { q.buffer.write(head, item) };
+                            let published = q.cell(head).cmpxchg(writing(), count, full(), count);
+                            debug_assert!(published.is_ok(), "claimed slot changed while writing");
+                            q.publish_head(head);
+                            break None;
+                        }
+                    }
+                    Err((count, state)) => {
+                        if state != full() {
+                            // the oldest slot is being written to or read from
+                            backoff.wait();
+                            continue;
+                        }
+
+                        // claim the oldest slot for the next round, which makes it the newest one
+                        let next_count = (count + 1) % components::Item::<T>::MAX_W;
+                        if q.cell(head)
+                            .cmpxchg(full(), count, writing(), next_count)
+                            .is_ok()
+                        {
+                            // Safety: the slot has been claimed by this thread
+                            let displaced = unsafe { q.buffer.read(head) };
+                            unsafe { q.buffer.write(head, item) };
+                            let published =
+                                q.cell(head)
+                                    .cmpxchg(writing(), next_count, full(), next_count);
+                            debug_assert!(published.is_ok(), "claimed slot changed while writing");
+                            q.publish_tail(head);
+                            q.publish_head(head);
+                            break Some(displaced);
+                        }
+                    }
+                }
+                backoff.wait();
+            };
+            q.leave();
+            Ok(displaced)
+        }
+
+        /// pop the last item, if an item is contained
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::InlineQueue;
+        ///
+        /// let q = InlineQueue::new(1);
+        /// assert_eq!(q.push(10), Ok(()));
+        ///
+        /// assert_eq!(q.pop(), Some(10));
+        /// assert!(q.pop().is_none());
+        /// ```
+        pub fn pop(&self) -> Option<T> {
+            let q = &self.0;
+            let mut backoff = B::default();
+            loop {
+                let (tail, count, state) = q.find_tail()?;
+                if state != full() {
+                    // the slot is being written to or read from
+                    backoff.wait();
+                    continue;
+                }
+
+                if q.cell(tail)
+                    .cmpxchg(full(), count, reading(), count)
+                    .is_ok()
+                {
+                    // Safety: the slot has been claimed by this thread
+                    let item = unsafe { q.buffer.read(tail) };
+                    let next_count = (count + 1) % components::Item::<T>::MAX_W;
+                    let released = q.cell(tail).cmpxchg(reading(), count, null(), next_count);
+                    debug_assert!(released.is_ok(), "claimed slot changed while reading");
+                    q.publish_tail(tail);
+                    return Some(item);
+                }
+                backoff.wait();
+            }
+        }
+
+        /// Pushes items from `items`, until it is exhausted or the queue is full or closed.
+        /// The items are pushed into consecutive slots with a single scan, and the head is published once.
+        /// Returns the number of pushed items and the item which could not be pushed, if any.
+        /// The remaining items are left in `items`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{InlineQueue, PushError};
+        ///
+        /// let q = InlineQueue::new(3);
+        /// let mut items = 1..=5;
+        ///
+        /// assert_eq!(q.push_iter(&mut items), (3, Some(PushError::Full(4))));
+        /// assert_eq!(items.next(), Some(5));
+        /// assert_eq!(q.pop_n(5), [1, 2, 3]);
+        /// ```
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = T>,
+        ) -> (usize, Option<PushError<T>>) {
+            let q = &self.0;
+            q.push_batch_with(
+                || items.next(),
+                |idx, count, item| {
+                    if q.cell(idx)
+                        .cmpxchg(null(), count, writing(), count)
+                        .is_err()
+                    {
+                        return Err(item);
+                    }
+                    // Safety: the slot has been claimed by this thread
+                    unsafe { q.buffer.write(idx, item) };
+                    let published = q.cell(idx).cmpxchg(writing(), count, full(), count);
+                    debug_assert!(published.is_ok(), "claimed slot changed while writing");
+                    Ok(())
+                },
+            )
+        }
+
+        /// Pushes clones of `items`, until the queue is full or closed.
+        /// Returns the number of pushed items, the remaining ones are `&items[n..]`.
+        pub fn push_slice(&self, items: &[T]) -> usize
+        where
+            T: Clone,
+        {
+            self.push_iter(&mut items.iter().cloned()).0
+        }
+
+        /// Pops up to `max` items into `out`.
+        /// The items are popped from consecutive slots with a single scan, and the tail is published once.
+        /// Returns the number of popped items.
+        pub fn pop_into(&self, out: &mut impl Extend<T>, max: usize) -> usize {
+            let q = &self.0;
+            q.pop_batch_with(
+                max,
+                |idx, count, state| {
+                    if state != full() {
+                        // the slot is being written to or read from, the batch waits and retries
+                        return None;
+                    }
+                    q.cell(idx).cmpxchg(full(), count, reading(), count).ok()?;
+                    // Safety: the slot has been claimed by this thread
+                    let item = unsafe { q.buffer.read(idx) };
+                    let next_count = (count + 1) % components::Item::<T>::MAX_W;
+                    let released = q.cell(idx).cmpxchg(reading(), count, null(), next_count);
+                    debug_assert!(released.is_ok(), "claimed slot changed while reading");
+                    Some(item)
+                },
+                |item| out.extend(Some(item)),
+            )
+        }
+
+        /// Pops up to `n` items.
+        pub fn pop_n(&self, n: usize) -> Vec<T> {
+            let mut items = Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop an item.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{InlineQueue, PopError};
+        ///
+        /// let q = InlineQueue::new(2);
+        /// assert_eq!(q.try_pop(), Err(PopError::Empty));
+        ///
+        /// q.push(10).unwrap();
+        /// q.close();
+        /// assert_eq!(q.try_pop(), Ok(10));
+        /// assert_eq!(q.try_pop(), Err(PopError::Closed));
+        /// ```
+        pub fn try_pop(&self) -> Result<T, PopError> {
+            self.0.try_pop_with(|| self.pop())
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining items can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff> Debug for InlineQueue<T, L, B> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.pad("InlineQueue { ... }")
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff> Drop for InlineQueue<T, L, B> {
+        fn drop(&mut self) {
+            // drop all remaining items
+            while self.pop().is_some() {}
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff> IntoIterator for InlineQueue<T, L, B> {
+        type Item = T;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: InlineQueue sends owned T's between threads.
+    /// It is only safe to do so, if T is Send
+    unsafe impl<T: Send, L: CellLayout, B: Backoff> Sync for InlineQueue<T, L, B> {}
+    unsafe impl<T: Send, L: CellLayout, B: Backoff> Send for InlineQueue<T, L, B> {}
+}
+
+#[cfg(feature = "alloc")]
+mod arc {
+    use super::*;
+    use alloc::{alloc::Global, sync::Arc, vec::Vec};
+
+    /// A bounded, heap-allocated queue of `Arc<T>`'s.
+    ///
+    /// The pointers of the `Arc`'s are stored directly in the cells, they are transferred with `Arc::into_raw`
+    /// and `Arc::from_raw` without touching the reference count.
+    /// The cells are laid out according to `L`, see `CellLayout`, and encoded according to `S`, see `Storage`.
+    pub struct ArcQueue<T, L: CellLayout = Dense, B: Backoff = Spin, S: Storage = DefaultStorage>(
+        ArrayQueue<T, components::FixedBuf<T, Global, L, S>, B>,
+    );
+
+    /// Takes back the reference, which has been moved into the queue.
+    ///
+    /// # Safety
+    ///
+    /// `item` must have been returned by `Arc::into_raw` and must not be used afterwards.
+    unsafe fn from_raw<T>(item: *const T) -> Arc<T> {
+        unsafe { Arc::from_raw(item) }
+    }
+
+    impl<T> ArcQueue<T> {
+        pub fn new(size: usize) -> Self {
+            Self::with_layout(size)
+        }
+
+        /// Creates a queue, whose capacity is `size` rounded up to the next power of two.
+        ///
+        /// Any queue with a power of two capacity wraps its indices with a mask instead of a division.
+        /// Use `new` if the capacity has to be exactly `size`.
+        pub fn new_pow2(size: usize) -> Self {
+            Self::new(size.next_power_of_two())
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> ArcQueue<T, L, B, S> {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L`
+        /// and encoded according to `S`, and which waits with `B` between retries.
+        pub fn with_layout(size: usize) -> Self {
+            assert!(size > 0, "Size of the queue must be greater than 0");
+            Self(ArrayQueue::new_in(components::FixedBuf::new_in(
+                size, Global,
+            )))
+        }
+
+        /// Attempts to push an `Arc` into the queue.
+        /// Returns the `Arc` as an error if the queue is full or closed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::ArcQueue;
+        /// use std::sync::Arc;
+        ///
+        /// let q = ArcQueue::new(2);
+        /// let item = Arc::new(10);
+        ///
+        /// assert!(q.push(item.clone()).is_ok());
+        /// assert!(Arc::ptr_eq(&q.pop().unwrap(), &item));
+        /// ```
+        pub fn push(&self, item: Arc<T>) -> Result<(), PushError<Arc<T>>> {
+            self.0
+                .push(Arc::into_raw(item))
+                .map_err(|err| err.map(|item| unsafe { from_raw(item) }))
+        }
+
+        /// Pushes an `Arc` into the queue, overwriting the oldest one if it is full.
+        /// Returns the `Arc` as an error if the queue is closed.
+        pub fn force_push(&self, item: Arc<T>) -> Result<Option<Arc<T>>, Arc<T>> {
+            match self.0.force_push(Arc::into_raw(item)) {
+                Ok(displaced) => Ok(displaced.map(|item| unsafe { from_raw(item) })),
+                Err(item) => Err(unsafe { from_raw(item) }),
+            }
+        }
+
+        /// pop the last `Arc`, if one is contained
+        pub fn pop(&self) -> Option<Arc<T>> {
+            self.0.pop().map(|item| unsafe { from_raw(item) })
+        }
+
+        /// Pushes `Arc`'s from `items`, until it is exhausted or the queue is full or closed.
+        /// Returns the number of pushed `Arc`'s and the one which could not be pushed, if any.
+        /// The remaining `Arc`'s are left in `items`.
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = Arc<T>>,
+        ) -> (usize, Option<PushError<Arc<T>>>) {
+            let (pushed, rejected) = self.0.push_batch(|| items.next().map(Arc::into_raw));
+            let rejected = rejected.map(|err| err.map(|item| unsafe { from_raw(item) }));
+            (pushed, rejected)
+        }
+
+        /// Pops up to `max` `Arc`'s into `out`.
+        /// Returns the number of popped `Arc`'s.
+        pub fn pop_into(&self, out: &mut impl Extend<Arc<T>>, max: usize) -> usize {
+            self.0.pop_batch(max, |item| {
+                out.extend(Some(unsafe { from_raw(item) }));
+            })
+        }
+
+        /// Pops up to `n` `Arc`'s.
+        pub fn pop_n(&self, n: usize) -> Vec<Arc<T>> {
+            let mut items = Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop an `Arc`.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        pub fn try_pop(&self) -> Result<Arc<T>, PopError> {
+            self.0.try_pop().map(|item| unsafe { from_raw(item) })
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining `Arc`'s can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> Debug for ArcQueue<T, L, B, S> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.pad("ArcQueue { ... }")
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> Drop for ArcQueue<T, L, B, S> {
+        fn drop(&mut self) {
+            // release the references held by the queue
+            while self.pop().is_some() {}
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> IntoIterator for ArcQueue<T, L, B, S> {
+        type Item = Arc<T>;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: ArcQueue sends `Arc<T>`'s between threads.
+    /// It is only safe to do so, if `Arc<T>` is Send, i.e. if T is Send + Sync
+    unsafe impl<T: Send + Sync, L: CellLayout, B: Backoff, S: Storage> Sync for ArcQueue<T, L, B, S> {}
+    unsafe impl<T: Send + Sync, L: CellLayout, B: Backoff, S: Storage> Send for ArcQueue<T, L, B, S> {}
+}
+
+#[cfg(feature = "alloc")]
+mod raw {
+    use super::*;
+    use alloc::{alloc::Global, vec::Vec};
+    use core::ptr::NonNull;
+
+    /// A bounded, heap-allocated queue of raw `NonNull<T>`'s, e.g. for intrusive data structures.
+    ///
+    /// The queue never dereferences, allocates or frees the pointers, their ownership is up to the caller.
+    /// Pointers, which are still in the queue when it is dropped, are leaked.
+    /// The cells are laid out according to `L`, see `CellLayout`, and encoded according to `S`, see `Storage`.
+    /// The queue is only `Send` and `Sync`, if `T` is `Send`.
+    ///
+    /// ```compile_fail
+    /// use nblfq::RawQueue;
+    /// use std::rc::Rc;
+    ///
+    /// fn shared<T: Sync>(_: &T) {}
+    ///
+    /// let q = RawQueue::<Rc<i32>>::new(2);
+    /// shared(&q);
+    /// ```
+    pub struct RawQueue<T, L: CellLayout = Dense, B: Backoff = Spin, S: Storage = DefaultStorage>(
+        ArrayQueue<T, components::FixedBuf<T, Global, L, S>, B>,
+    );
+
+    /// Restores a pointer taken from an occupied cell.
+    fn non_null<T>(item: *const T) -> NonNull<T> {
+        // Safety: only empty cells hold null
+        unsafe { NonNull::new_unchecked(item as *mut T) }
+    }
+
+    impl<T> RawQueue<T> {
+        pub fn new(size: usize) -> Self {
+            Self::with_layout(size)
+        }
+
+        /// Creates a queue, whose capacity is `size` rounded up to the next power of two.
+        ///
+        /// Any queue with a power of two capacity wraps its indices with a mask instead of a division.
+        /// Use `new` if the capacity has to be exactly `size`.
+        pub fn new_pow2(size: usize) -> Self {
+            Self::new(size.next_power_of_two())
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> RawQueue<T, L, B, S> {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L`
+        /// and encoded according to `S`, and which waits with `B` between retries.
+        pub fn with_layout(size: usize) -> Self {
+            assert!(size > 0, "Size of the queue must be greater than 0");
+            Self(ArrayQueue::new_in(components::FixedBuf::new_in(
+                size, Global,
+            )))
+        }
+
+        /// Attempts to push a pointer into the queue.
+        /// Returns the pointer as an error if the queue is full or closed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::RawQueue;
+        /// use std::ptr::NonNull;
+        ///
+        /// let q = RawQueue::new(2);
+        /// let item = NonNull::from(Box::leak(Box::new(10)));
+        ///
+        /// assert_eq!(q.push(item), Ok(()));
+        /// let item = q.pop().unwrap();
+        /// assert_eq!(unsafe { *Box::from_raw(item.as_ptr()) }, 10);
+        /// ```
+        pub fn push(&self, item: NonNull<T>) -> Result<(), PushError<NonNull<T>>> {
+            self.0.push(item.as_ptr()).map_err(|err| err.map(non_null))
+        }
+
+        /// Pushes a pointer into the queue, overwriting the oldest one if it is full.
+        /// Returns the pointer as an error if the queue is closed.
+        pub fn force_push(&self, item: NonNull<T>) -> Result<Option<NonNull<T>>, NonNull<T>> {
+            self.0
+                .force_push(item.as_ptr())
+                .map(|displaced| displaced.map(non_null))
+                .map_err(non_null)
+        }
+
+        /// pop the last pointer, if one is contained
+        pub fn pop(&self) -> Option<NonNull<T>> {
+            self.0.pop().map(non_null)
+        }
+
+        /// Pushes pointers from `items`, until it is exhausted or the queue is full or closed.
+        /// Returns the number of pushed pointers and the one which could not be pushed, if any.
+        /// The remaining pointers are left in `items`.
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = NonNull<T>>,
+        ) -> (usize, Option<PushError<NonNull<T>>>) {
+            let (pushed, rejected) = self
+                .0
+                .push_batch(|| items.next().map(|item| item.as_ptr() as *const T));
+            (pushed, rejected.map(|err| err.map(non_null)))
+        }
+
+        /// Pops up to `max` pointers into `out`.
+        /// Returns the number of popped pointers.
+        pub fn pop_into(&self, out: &mut impl Extend<NonNull<T>>, max: usize) -> usize {
+            self.0
+                .pop_batch(max, |item| out.extend(Some(non_null(item))))
+        }
+
+        /// Pops up to `n` pointers.
+        pub fn pop_n(&self, n: usize) -> Vec<NonNull<T>> {
+            let mut items = Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop a pointer.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        pub fn try_pop(&self) -> Result<NonNull<T>, PopError> {
+            self.0.try_pop().map(non_null)
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining pointers can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> Debug for RawQueue<T, L, B, S> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.pad("RawQueue { ... }")
+        }
+    }
+
+    impl<T, L: CellLayout, B: Backoff, S: Storage> IntoIterator for RawQueue<T, L, B, S> {
+        type Item = NonNull<T>;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: RawQueue never dereferences its pointers, it only hands them between threads.
+    /// Handing over a `NonNull<T>` moves access to the T to another thread, which is only safe if T is Send.
+    unsafe impl<T: Send, L: CellLayout, B: Backoff, S: Storage> Sync for RawQueue<T, L, B, S> {}
+    unsafe impl<T: Send, L: CellLayout, B: Backoff, S: Storage> Send for RawQueue<T, L, B, S> {}
+}
+
+#[cfg(feature = "alloc")]
+mod index {
+    use super::*;
+    use alloc::{alloc::Global, vec::Vec};
+    use core::ptr::without_provenance;
+
+    // Each value is stored as a pointer without provenance, which is never dereferenced.
+    // The value is offset by one, so that null still marks an empty cell.
+
+    fn encode(value: u32) -> *const () {
+        assert!(value != u32::MAX, "u32::MAX is reserved for empty cells");
+        without_provenance(value as usize + 1)
+    }
+
+    fn decode(ptr: *const ()) -> u32 {
+        (ptr.addr() - 1) as u32
+    }
+
+    /// A bounded, heap-allocated queue of `u32` values, such as indices into a table.
+    ///
+    /// Each value is stored directly in its cell, next to the round count, in an `AtomicU64`.
+    /// Unlike `HeapBackedQueue`, pushing and popping never allocate.
+    /// `u32::MAX` encodes an empty cell and cannot be pushed.
+    /// The cells are laid out according to `L`, see `CellLayout`.
+    pub struct IndexQueue<L: CellLayout = Dense, B: Backoff = Spin>(
+        ArrayQueue<(), components::FixedBuf<(), Global, L, components::Index>, B>,
+    );
+
+    impl IndexQueue {
+        pub fn new(size: usize) -> Self {
+            Self::with_layout(size)
+        }
+
+        /// Creates a queue, whose capacity is `size` rounded up to the next power of two.
+        ///
+        /// Any queue with a power of two capacity wraps its indices with a mask instead of a division.
+        /// Use `new` if the capacity has to be exactly `size`.
+        pub fn new_pow2(size: usize) -> Self {
+            Self::new(size.next_power_of_two())
+        }
+    }
+
+    impl<L: CellLayout, B: Backoff> IndexQueue<L, B> {
+        /// Creates a queue of capacity `size`, whose cells are laid out according to `L`
+        /// and which waits with `B` between retries.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{IndexQueue, Padded};
+        ///
+        /// let q: IndexQueue<Padded> = IndexQueue::with_layout(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn with_layout(size: usize) -> Self {
+            assert!(size > 0, "Size of the queue must be greater than 0");
+            Self(ArrayQueue::new_in(components::FixedBuf::new_in(
+                size, Global,
+            )))
+        }
+
+        /// Attempts to push a value into the queue.
+        /// Returns the value as an error if the queue is full or closed.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `value` is `u32::MAX`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{IndexQueue, PushError};
+        ///
+        /// let q = IndexQueue::new(2);
+        ///
+        /// assert_eq!(q.push(10), Ok(()));
+        /// assert_eq!(q.push(20), Ok(()));
+        /// assert_eq!(q.push(30), Err(PushError::Full(30)));
+        /// assert_eq!(q.pop(), Some(10));
+        /// ```
+        pub fn push(&self, value: u32) -> Result<(), PushError<u32>> {
+            self.0.push(encode(value)).map_err(|err| err.map(decode))
+        }
+
+        /// Pushes a value into the queue, overwriting the oldest value if it is full.
+        /// Returns the value as an error if the queue is closed.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `value` is `u32::MAX`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::IndexQueue;
+        ///
+        /// let q = IndexQueue::new(2);
+        ///
+        /// assert_eq!(q.force_push(10), Ok(None));
+        /// assert_eq!(q.force_push(20), Ok(None));
+        /// assert_eq!(q.force_push(30), Ok(Some(10)));
+        /// assert_eq!(q.pop(), Some(20));
+        /// ```
+        pub fn force_push(&self, value: u32) -> Result<Option<u32>, u32> {
+            self.0
+                .force_push(encode(value))
+                .map(|displaced| displaced.map(decode))
+                .map_err(decode)
+        }
+
+        /// pop the last value, if a value is contained
+        pub fn pop(&self) -> Option<u32> {
+            self.0.pop().map(decode)
+        }
+
+        /// Pushes values from `values`, until it is exhausted or the queue is full or closed.
+        /// Returns the number of pushed values and the value which could not be pushed, if any.
+        /// The remaining values are left in `values`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if a value is `u32::MAX`.
+        pub fn push_iter(
+            &self,
+            values: &mut impl Iterator<Item = u32>,
+        ) -> (usize, Option<PushError<u32>>) {
+            let (pushed, rejected) = self.0.push_batch(|| values.next().map(encode));
+            (pushed, rejected.map(|err| err.map(decode)))
+        }
+
+        /// Pushes `values`, until the queue is full or closed.
+        /// Returns the number of pushed values, the remaining ones are `&values[n..]`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if a value is `u32::MAX`.
+        pub fn push_slice(&self, values: &[u32]) -> usize {
+            self.push_iter(&mut values.iter().copied()).0
+        }
+
+        /// Pops up to `max` values into `out`.
+        /// Returns the number of popped values.
+        pub fn pop_into(&self, out: &mut impl Extend<u32>, max: usize) -> usize {
+            self.0
+                .pop_batch(max, |value| out.extend(Some(decode(value))))
+        }
+
+        /// Pops up to `n` values.
+        pub fn pop_n(&self, n: usize) -> Vec<u32> {
+            let mut values = Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut values, n);
+            values
+        }
+
+        /// Attempts to pop a value.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        pub fn try_pop(&self) -> Result<u32, PopError> {
+            self.0.try_pop().map(decode)
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining values can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    impl<L: CellLayout, B: Backoff> Debug for IndexQueue<L, B> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.pad("IndexQueue { ... }")
+        }
+    }
+
+    impl<L: CellLayout, B: Backoff> IntoIterator for IndexQueue<L, B> {
+        type Item = u32;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: IndexQueue only holds plain values, the cell pointers are never dereferenced
+    unsafe impl<L: CellLayout, B: Backoff> Sync for IndexQueue<L, B> {}
+    unsafe impl<L: CellLayout, B: Backoff> Send for IndexQueue<L, B> {}
+}
+
+mod heapless {
+    use super::*;
+
+    /// A bounded queue of `&'static T`'s, which does not allocate.
+    ///
+    /// If `N` is a power of two, indices are wrapped with a mask instead of a division.
+    /// The cells are laid out according to `L`, see `CellLayout`, and encoded according to `S`, see `Storage`.
+    pub struct HeaplessQueue<
+        const N: usize,
+        T,
+        L: CellLayout = Dense,
+        B: Backoff = Spin,
+        S: Storage = DefaultStorage,
+    >(ArrayQueue<T, components::HeaplessBuf<N, T, L, S>, B>);
+
+    impl<const N: usize, T> HeaplessQueue<N, T> {
+        /// Creates an empty queue.
+        ///
+        /// This function is const, so the queue can be placed in a `static`.
+        /// A size of 0 is rejected at compile time.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// static Q: HeaplessQueue<2, i32> = HeaplessQueue::new();
+        ///
+        /// assert_eq!(Q.push(&10), Ok(()));
+        /// assert_eq!(Q.pop(), Some(&10));
+        /// ```
+        ///
+        /// ```compile_fail
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<0, i32> = HeaplessQueue::new();
+        /// ```
+        pub const fn new() -> Self {
+            Self::with_layout()
+        }
+    }
+
+    impl<const N: usize, T, L: CellLayout, B: Backoff, S: Storage> HeaplessQueue<N, T, L, B, S> {
+        /// Creates an empty queue, whose cells are laid out according to `L`
+        /// and encoded according to `S`, and which waits with `B` between retries.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeaplessQueue, Padded};
+        ///
+        /// static Q: HeaplessQueue<2, i32, Padded> = HeaplessQueue::with_layout();
+        ///
+        /// assert_eq!(Q.push(&10), Ok(()));
+        /// assert_eq!(Q.pop(), Some(&10));
+        /// ```
+        pub const fn with_layout() -> Self {
+            const { assert!(N > 0, "Size of the queue must be greater than 0") };
+            Self(ArrayQueue::new_in(components::HeaplessBuf::new()))
+        }
+
+        /// Attempts to push an item into the queue.
+        /// Returns the item as an error if the queue is full or closed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeaplessQueue, PushError};
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        ///
+        /// assert_eq!(q.push(&10), Ok(()));
+        /// assert_eq!(q.push(&20), Ok(()));
+        /// assert_eq!(q.push(&30), Err(PushError::Full(&30)));
+        /// assert_eq!(q.pop(), Some(&10));
+        /// ```
+        pub fn push(&self, item: &'static T) -> Result<(), PushError<&'static T>> {
+            let item = item as *const T;
+            self.0
+                .push(item)
+                .map_err(|err| err.map(|item| unsafe { &*item }))
+        }
+
+        /// Pushes an item into the queue, overwriting the oldest item if it is full
+        /// The oldest item is displaced atomically, with the same kind of cell CAS as push() and pop().
+        /// Concurrent calls therefore evict one item each, and the evicted item is returned
+        /// Returns the item as an error if the queue is closed
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        ///
+        /// assert_eq!(q.force_push(&10), Ok(None));
+        /// assert_eq!(q.force_push(&20), Ok(None));
+        /// assert_eq!(q.force_push(&30), Ok(Some(&10)));
+        /// assert_eq!(q.pop(), Some(&20));
+        /// ```
+        pub fn force_push(&self, item: &'static T) -> Result<Option<&'static T>, &'static T> {
+            match self.0.force_push(item) {
+                Ok(displaced) => Ok(displaced.map(|item| unsafe { &*item })),
+                Err(item) => Err(unsafe { &*item }),
+            }
+        }
+
+        /// pop the last item, if an item is contained
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<1, _> = HeaplessQueue::new();
+        /// assert_eq!(q.push(&10), Ok(()));
         ///
         /// assert_eq!(q.pop(), Some(&10));
         /// assert!(q.pop().is_none());
@@ -423,6 +2456,235 @@ mod heapless {
             self.0.pop().map(|item| unsafe { &*item })
         }
 
+        /// Returns the oldest item without popping it, or None if the queue is empty.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// assert_eq!(q.peek(), None);
+        ///
+        /// q.push(&10).unwrap();
+        /// assert_eq!(q.peek(), Some(&10));
+        /// assert_eq!(q.pop(), Some(&10));
+        /// ```
+        pub fn peek(&self) -> Option<&'static T> {
+            // Safety: every item has been pushed as a &'static T, so it outlives any pop
+            self.0.peek().map(|(_, _, item)| unsafe { &*item })
+        }
+
+        /// Pops the items, which are in the queue when it is called, see `HeapBackedQueue::clear`.
+        pub fn clear(&self)
+        where
+            T: 'static,
+        {
+            self.drain().for_each(drop);
+        }
+
+        /// Returns an iterator, which pops the items that are in the queue when it is called,
+        /// see `HeapBackedQueue::drain`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<3, _> = HeaplessQueue::new();
+        /// q.push(&10).unwrap();
+        ///
+        /// let mut drain = q.drain();
+        /// q.push(&20).unwrap();
+        /// assert_eq!(drain.next(), Some(&10));
+        /// assert_eq!(drain.next(), None);
+        /// assert_eq!(q.pop(), Some(&20));
+        /// ```
+        pub fn drain(&self) -> impl Iterator<Item = &'static T>
+        where
+            T: 'static,
+        {
+            let end = self.0.end();
+            iter::from_fn(move || self.0.pop_before(end).map(|item| unsafe { &*item }))
+        }
+
+        /// Removes the items in the queue, for which `f` returns false, and re-enqueues the others,
+        /// see `HeapBackedQueue::retain`.
+        pub fn retain(&self, mut f: impl FnMut(&T) -> bool) -> Result<(), PushError<&'static T>>
+        where
+            T: 'static,
+        {
+            for item in self.drain() {
+                if f(item) {
+                    self.push(item)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Returns an iterator over the items in the queue, from the oldest to the newest.
+        ///
+        /// The cells are walked from the tail towards the head. Items popped or pushed during the walk may be missing or included,
+        /// the iterator yields a best-effort snapshot, which does not modify the queue.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<3, _> = HeaplessQueue::new();
+        /// q.push(&10).unwrap();
+        /// q.push(&20).unwrap();
+        ///
+        /// assert!(q.iter().eq([&10, &20]));
+        /// assert_eq!(q.len(), 2);
+        /// ```
+        pub fn iter(&self) -> impl Iterator<Item = &'static T>
+        where
+            T: 'static,
+        {
+            self.0.items().map(|(_, _, item)| unsafe { &*item })
+        }
+
+        /// Returns a value, whose `Debug` output lists a best-effort snapshot of the items, see `iter`, along with the state of the ring.
+        ///
+        /// The `Debug` output of the queue itself only shows the capacity, the head and tail hints and the round count of every cell.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// q.push(&10).unwrap();
+        ///
+        /// assert_eq!(
+        ///     format!("{:?}", q.debug_items()),
+        ///     "HeaplessQueue { items: [10], capacity: 2, head: 1, tail: 0, counts: [0, 0] }"
+        /// );
+        /// ```
+        pub fn debug_items(&self) -> impl Debug + '_
+        where
+            T: Debug + 'static,
+        {
+            core::fmt::from_fn(move |f| {
+                let items = core::fmt::from_fn(|f| f.debug_list().entries(self.iter()).finish());
+                self.0.fmt_as("HeaplessQueue", Some(&items), f)
+            })
+        }
+
+        /// Pops the oldest item, if `pred` returns true for it.
+        /// Returns None if the queue is empty or `pred` returns false.
+        ///
+        /// The item is popped with a CAS against the round count of its cell, as observed before calling `pred`,
+        /// so another consumer cannot take it in between. If it does take it first, `pred` is called for the next item.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// q.push(&10).unwrap();
+        ///
+        /// assert_eq!(q.pop_if(|item| *item > 10), None);
+        /// assert_eq!(q.pop_if(|item| *item == 10), Some(&10));
+        /// ```
+        pub fn pop_if(&self, mut pred: impl FnMut(&T) -> bool) -> Option<&'static T> {
+            self.0
+                .pop_if(|_, _, item| pred(unsafe { &*item }))
+                .map(|item| unsafe { &*item })
+        }
+
+        /// Pushes items from `items`, until it is exhausted or the queue is full or closed.
+        /// The items are pushed into consecutive cells with a single scan, and the head is published once.
+        /// Returns the number of pushed items and the item which could not be pushed, if any.
+        /// The remaining items are left in `items`.
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = &'static T>,
+        ) -> (usize, Option<PushError<&'static T>>) {
+            let (pushed, rejected) = self
+                .0
+                .push_batch(|| items.next().map(|item| item as *const T));
+            (
+                pushed,
+                rejected.map(|err| err.map(|item| unsafe { &*item })),
+            )
+        }
+
+        /// Pushes references to `items`, until the queue is full or closed.
+        /// Returns the number of pushed items, the remaining ones are `&items[n..]`.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::HeaplessQueue;
+        ///
+        /// static ITEMS: [i32; 3] = [1, 2, 3];
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        ///
+        /// assert_eq!(q.push_slice(&ITEMS), 2);
+        /// assert_eq!(q.pop(), Some(&1));
+        /// ```
+        pub fn push_slice(&self, items: &'static [T]) -> usize {
+            self.push_iter(&mut items.iter()).0
+        }
+
+        /// Pops up to `max` items into `out`.
+        /// The items are popped from consecutive cells with a single scan, and the tail is published once.
+        /// Returns the number of popped items.
+        pub fn pop_into(&self, out: &mut impl Extend<&'static T>, max: usize) -> usize
+        where
+            T: 'static,
+        {
+            self.0
+                .pop_batch(max, |item| out.extend(Some(unsafe { &*item })))
+        }
+
+        /// Pops up to `n` items.
+        #[cfg(feature = "alloc")]
+        pub fn pop_n(&self, n: usize) -> alloc::vec::Vec<&'static T>
+        where
+            T: 'static,
+        {
+            let mut items = alloc::vec::Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop an item.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use nblfq::{HeaplessQueue, PopError};
+        ///
+        /// let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+        /// assert_eq!(q.try_pop(), Err(PopError::Empty));
+        ///
+        /// q.push(&10).unwrap();
+        /// q.close();
+        /// assert_eq!(q.try_pop(), Ok(&10));
+        /// assert_eq!(q.try_pop(), Err(PopError::Closed));
+        /// ```
+        pub fn try_pop(&self) -> Result<&'static T, PopError> {
+            self.0.try_pop().map(|item| unsafe { &*item })
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining items can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
         /// Returns the total capacity of the underlying buffer.
         pub fn capacity(&self) -> usize {
             self.0.capacity()
@@ -447,20 +2709,26 @@ mod heapless {
         }
     }
 
-    impl<const N: usize, T> Default for HeaplessQueue<N, T> {
+    impl<const N: usize, T, L: CellLayout, B: Backoff, S: Storage> Default
+        for HeaplessQueue<N, T, L, B, S>
+    {
         fn default() -> Self {
-            assert!(N > 0, "Size of the queue must be greater than 0");
-            Self::new()
+            Self::with_layout()
         }
     }
 
-    impl<const N: usize, T> Debug for HeaplessQueue<N, T> {
+    /// Shows the state of the ring without the items, see `HeaplessQueue::debug_items`.
+    impl<const N: usize, T, L: CellLayout, B: Backoff, S: Storage> Debug
+        for HeaplessQueue<N, T, L, B, S>
+    {
         fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-            f.pad("HeaplessQueue { ... }")
+            self.0.fmt_as("HeaplessQueue", None, f)
         }
     }
 
-    impl<const N: usize, T: 'static> IntoIterator for HeaplessQueue<N, T> {
+    impl<const N: usize, T: 'static, L: CellLayout, B: Backoff, S: Storage> IntoIterator
+        for HeaplessQueue<N, T, L, B, S>
+    {
         type Item = &'static T;
         type IntoIter = impl Iterator<Item = Self::Item>;
 
@@ -471,6 +2739,289 @@ mod heapless {
 
     /// Safety: HeaplessQueue sends static ref T's between threads.
     /// It is only safe to do so if T is Sync
-    unsafe impl<const N: usize, T: Sync> Sync for HeaplessQueue<N, T> {}
-    unsafe impl<const N: usize, T: Sync> Send for HeaplessQueue<N, T> {}
+    unsafe impl<const N: usize, T: Sync, L: CellLayout, B: Backoff, S: Storage> Sync
+        for HeaplessQueue<N, T, L, B, S>
+    {
+    }
+    unsafe impl<const N: usize, T: Sync, L: CellLayout, B: Backoff, S: Storage> Send
+        for HeaplessQueue<N, T, L, B, S>
+    {
+    }
+}
+
+mod borrow {
+    use super::*;
+
+    /// A bounded queue of `&'a T`'s, which does not allocate.
+    ///
+    /// Unlike `HeaplessQueue`, the items only have to outlive the queue, e.g. data owned by the stack frame,
+    /// which spawns the threads of a `std::thread::scope`.
+    /// The queue is invariant in `'a`, so it never hands out references, which were pushed with a shorter lifetime.
+    /// The cells are laid out according to `L`, see `CellLayout`, and encoded according to `S`, see `Storage`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::BorrowQueue;
+    /// use std::thread;
+    ///
+    /// let items = [1, 2, 3];
+    /// let q: BorrowQueue<4, i32> = BorrowQueue::new();
+    ///
+    /// thread::scope(|scope| {
+    ///     scope.spawn(|| {
+    ///         for item in &items {
+    ///             q.push(item).unwrap();
+    ///         }
+    ///     });
+    /// });
+    ///
+    /// assert_eq!(q.pop(), Some(&1));
+    /// ```
+    ///
+    /// An item must outlive the queue:
+    ///
+    /// ```compile_fail
+    /// use nblfq::BorrowQueue;
+    ///
+    /// let q: BorrowQueue<2, i32> = BorrowQueue::new();
+    /// {
+    ///     let item = 1;
+    ///     q.push(&item).unwrap();
+    /// }
+    /// q.pop();
+    /// ```
+    ///
+    /// The lifetime cannot be shortened, which would allow pushing shorter lived items:
+    ///
+    /// ```compile_fail
+    /// use nblfq::BorrowQueue;
+    ///
+    /// fn shorten<'s, 'l: 's>(q: &'s BorrowQueue<'l, 2, i32>) -> &'s BorrowQueue<'s, 2, i32> {
+    ///     q
+    /// }
+    /// ```
+    pub struct BorrowQueue<
+        'a,
+        const N: usize,
+        T,
+        L: CellLayout = Dense,
+        B: Backoff = Spin,
+        S: Storage = DefaultStorage,
+    >(
+        ArrayQueue<T, components::HeaplessBuf<N, T, L, S>, B>,
+        PhantomData<fn(&'a T) -> &'a T>,
+    );
+
+    impl<'a, const N: usize, T> BorrowQueue<'a, N, T> {
+        /// Creates an empty queue.
+        ///
+        /// A size of 0 is rejected at compile time.
+        pub const fn new() -> Self {
+            Self::with_layout()
+        }
+    }
+
+    impl<'a, const N: usize, T, L: CellLayout, B: Backoff, S: Storage> BorrowQueue<'a, N, T, L, B, S> {
+        /// Creates an empty queue, whose cells are laid out according to `L`
+        /// and encoded according to `S`, and which waits with `B` between retries.
+        pub const fn with_layout() -> Self {
+            const { assert!(N > 0, "Size of the queue must be greater than 0") };
+            Self(
+                ArrayQueue::new_in(components::HeaplessBuf::new()),
+                PhantomData,
+            )
+        }
+
+        /// Attempts to push an item into the queue.
+        /// Returns the item as an error if the queue is full or closed.
+        pub fn push(&self, item: &'a T) -> Result<(), PushError<&'a T>> {
+            self.0
+                .push(item)
+                .map_err(|err| err.map(|item| unsafe { &*item }))
+        }
+
+        /// Pushes an item into the queue, overwriting the oldest item if it is full.
+        /// Returns the item as an error if the queue is closed.
+        pub fn force_push(&self, item: &'a T) -> Result<Option<&'a T>, &'a T> {
+            match self.0.force_push(item) {
+                Ok(displaced) => Ok(displaced.map(|item| unsafe { &*item })),
+                Err(item) => Err(unsafe { &*item }),
+            }
+        }
+
+        /// pop the last item, if an item is contained
+        pub fn pop(&self) -> Option<&'a T> {
+            // Safety: every item has been pushed as a &'a T
+            self.0.pop().map(|item| unsafe { &*item })
+        }
+
+        /// Returns the oldest item without popping it, or None if the queue is empty.
+        pub fn peek(&self) -> Option<&'a T> {
+            self.0.peek().map(|(_, _, item)| unsafe { &*item })
+        }
+
+        /// Pops the items, which are in the queue when it is called, see `HeapBackedQueue::clear`.
+        pub fn clear(&self) {
+            self.drain().for_each(drop);
+        }
+
+        /// Returns an iterator, which pops the items that are in the queue when it is called,
+        /// see `HeapBackedQueue::drain`.
+        pub fn drain(&self) -> impl Iterator<Item = &'a T> {
+            let end = self.0.end();
+            iter::from_fn(move || self.0.pop_before(end).map(|item| unsafe { &*item }))
+        }
+
+        /// Removes the items in the queue, for which `f` returns false, and re-enqueues the others,
+        /// see `HeapBackedQueue::retain`.
+        pub fn retain(&self, mut f: impl FnMut(&T) -> bool) -> Result<(), PushError<&'a T>> {
+            for item in self.drain() {
+                if f(item) {
+                    self.push(item)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Returns an iterator over the items in the queue, see `HeaplessQueue::iter`.
+        pub fn iter(&self) -> impl Iterator<Item = &'a T> {
+            self.0.items().map(|(_, _, item)| unsafe { &*item })
+        }
+
+        /// Returns a value, whose `Debug` output lists the items along with the state of the ring, see `HeaplessQueue::debug_items`.
+        pub fn debug_items(&self) -> impl Debug + '_
+        where
+            T: Debug,
+        {
+            core::fmt::from_fn(move |f| {
+                let items = core::fmt::from_fn(|f| f.debug_list().entries(self.iter()).finish());
+                self.0.fmt_as("BorrowQueue", Some(&items), f)
+            })
+        }
+
+        /// Pops the oldest item, if `pred` returns true for it, see `HeaplessQueue::pop_if`.
+        pub fn pop_if(&self, mut pred: impl FnMut(&T) -> bool) -> Option<&'a T> {
+            self.0
+                .pop_if(|_, _, item| pred(unsafe { &*item }))
+                .map(|item| unsafe { &*item })
+        }
+
+        /// Pushes items from `items`, until it is exhausted or the queue is full or closed.
+        /// Returns the number of pushed items and the item which could not be pushed, if any.
+        /// The remaining items are left in `items`.
+        pub fn push_iter(
+            &self,
+            items: &mut impl Iterator<Item = &'a T>,
+        ) -> (usize, Option<PushError<&'a T>>) {
+            let (pushed, rejected) = self
+                .0
+                .push_batch(|| items.next().map(|item| item as *const T));
+            (
+                pushed,
+                rejected.map(|err| err.map(|item| unsafe { &*item })),
+            )
+        }
+
+        /// Pushes references to `items`, until the queue is full or closed.
+        /// Returns the number of pushed items, the remaining ones are `&items[n..]`.
+        pub fn push_slice(&self, items: &'a [T]) -> usize {
+            self.push_iter(&mut items.iter()).0
+        }
+
+        /// Pops up to `max` items into `out`.
+        /// Returns the number of popped items.
+        pub fn pop_into(&self, out: &mut impl Extend<&'a T>, max: usize) -> usize {
+            self.0
+                .pop_batch(max, |item| out.extend(Some(unsafe { &*item })))
+        }
+
+        /// Pops up to `n` items.
+        #[cfg(feature = "alloc")]
+        pub fn pop_n(&self, n: usize) -> alloc::vec::Vec<&'a T> {
+            let mut items = alloc::vec::Vec::with_capacity(n.min(self.capacity()));
+            self.pop_into(&mut items, n);
+            items
+        }
+
+        /// Attempts to pop an item.
+        /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+        pub fn try_pop(&self) -> Result<&'a T, PopError> {
+            self.0.try_pop().map(|item| unsafe { &*item })
+        }
+
+        /// Closes the queue.
+        /// Subsequent pushes fail with `PushError::Closed`, while the remaining items can still be popped.
+        /// Returns false if the queue has already been closed.
+        pub fn close(&self) -> bool {
+            self.0.close()
+        }
+
+        /// Indicates whether the queue has been closed.
+        pub fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        /// Returns the total capacity of the underlying buffer.
+        pub fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        /// Returns the current len of the queue.
+        /// This value may be stale.
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        /// Indicates whether the queue is empty.
+        /// The result may be stale.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// Indicates whether the queue is full.
+        /// The result may be stale.
+        pub fn is_full(&self) -> bool {
+            self.0.is_full()
+        }
+    }
+
+    impl<const N: usize, T, L: CellLayout, B: Backoff, S: Storage> Default
+        for BorrowQueue<'_, N, T, L, B, S>
+    {
+        fn default() -> Self {
+            Self::with_layout()
+        }
+    }
+
+    /// Shows the state of the ring without the items, see `BorrowQueue::debug_items`.
+    impl<const N: usize, T, L: CellLayout, B: Backoff, S: Storage> Debug
+        for BorrowQueue<'_, N, T, L, B, S>
+    {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            self.0.fmt_as("BorrowQueue", None, f)
+        }
+    }
+
+    impl<'a, const N: usize, T, L: CellLayout, B: Backoff, S: Storage> IntoIterator
+        for BorrowQueue<'a, N, T, L, B, S>
+    {
+        type Item = &'a T;
+        type IntoIter = impl Iterator<Item = Self::Item>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            iter::from_fn(move || self.pop())
+        }
+    }
+
+    /// Safety: BorrowQueue sends &'a T's between threads.
+    /// It is only safe to do so if T is Sync
+    unsafe impl<const N: usize, T: Sync, L: CellLayout, B: Backoff, S: Storage> Sync
+        for BorrowQueue<'_, N, T, L, B, S>
+    {
+    }
+    unsafe impl<const N: usize, T: Sync, L: CellLayout, B: Backoff, S: Storage> Send
+        for BorrowQueue<'_, N, T, L, B, S>
+    {
+    }
 }
@@ -0,0 +1,613 @@
+//! Async MPMC channels backed by a `HeapBackedQueue`.
+//!
+//! Senders wait while the queue is full and receivers wait while it is empty.
+//! Waiting tasks register their `Waker` in a lock-free list and are woken by the opposite operation,
+//! so the channels only require `alloc` and work with any executor.
+//!
+//! With the `futures` feature, `Receiver` implements `Stream` and `Sender` implements `Sink`.
+//!
+//! # Examples
+//!
+//! ```
+//! use nblfq::async_channel;
+//! # use std::{future::Future, pin::pin, sync::Arc, task::{Context, Poll, Wake, Waker}, thread};
+//! # struct Unpark(thread::Thread);
+//! # impl Wake for Unpark {
+//! #     fn wake(self: Arc<Self>) {
+//! #         self.0.unpark();
+//! #     }
+//! # }
+//! # fn block_on<F: Future>(fut: F) -> F::Output {
+//! #     let mut fut = pin!(fut);
+//! #     let waker = Waker::from(Arc::new(Unpark(thread::current())));
+//! #     let mut cx = Context::from_waker(&waker);
+//! #     loop {
+//! #         if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+//! #             return res;
+//! #         }
+//! #         thread::park();
+//! #     }
+//! # }
+//!
+//! let (tx, rx) = async_channel::bounded(2);
+//!
+//! let handle = thread::spawn(move || {
+//!     block_on(async {
+//!         for i in 0..10 {
+//!             tx.send(i).await.unwrap();
+//!         }
+//!     })
+//! });
+//!
+//! block_on(async {
+//!     for i in 0..10 {
+//!         assert_eq!(rx.recv().await, Ok(i));
+//!     }
+//!     // all senders have been dropped
+//!     assert!(rx.recv().await.is_err());
+//! });
+//! handle.join().unwrap();
+//! ```
+
+use alloc::sync::Arc;
+use core::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering, fence},
+    task::{Context, Poll, Waker},
+};
+
+pub use crate::error::{RecvError, SendError, TryRecvError, TrySendError};
+use crate::{HeapBackedQueue, UnboundedQueue};
+
+/// Creates a channel of capacity `cap`.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0.
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: HeapBackedQueue::new(cap),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        not_empty: WaitList::new(),
+        not_full: WaitList::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+            #[cfg(feature = "futures")]
+            sink: SinkState {
+                item: None,
+                registration: Registration::new(),
+                closed: false,
+            },
+        },
+        Receiver {
+            shared,
+            #[cfg(feature = "futures")]
+            stream: Registration::new(),
+        },
+    )
+}
+
+struct Shared<T> {
+    queue: HeapBackedQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    /// Receivers waiting for an item
+    not_empty: WaitList,
+    /// Senders waiting for a free slot
+    not_full: WaitList,
+}
+
+impl<T> Shared<T> {
+    fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        // the queue is closed once all receivers have been dropped
+        self.queue.push(item)?;
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        // the queue is closed once all senders have been dropped
+        let item = self.queue.try_pop()?;
+        self.not_full.notify_one();
+        Ok(item)
+    }
+
+    /// Sends the item in `slot`, putting it back if the channel is full.
+    fn send_from(&self, slot: &mut Option<T>) -> Option<Result<(), SendError<T>>> {
+        match self.try_send(slot.take().expect("item has been sent")) {
+            Ok(()) => Some(Ok(())),
+            Err(TrySendError::Disconnected(item)) => Some(Err(SendError(item))),
+            Err(TrySendError::Full(item)) => {
+                *slot = Some(item);
+                None
+            }
+        }
+    }
+
+    fn recv(&self) -> Option<Result<T, RecvError>> {
+        match self.try_recv() {
+            Ok(item) => Some(Ok(item)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+}
+
+const WAITING: u8 = 0;
+const NOTIFIED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// A task waiting for a condition on the queue.
+struct Waiter {
+    state: AtomicU8,
+    waker: Waker,
+}
+
+impl Waiter {
+    /// Wakes the task, returns false if it is no longer waiting.
+    fn notify(&self) -> bool {
+        let notified = self
+            .state
+            .compare_exchange(WAITING, NOTIFIED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if notified {
+            self.waker.wake_by_ref();
+        }
+        notified
+    }
+
+    /// Stops waiting, returns false if the task has already been notified.
+    fn cancel(&self) -> bool {
+        self.state
+            .compare_exchange(WAITING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+/// A lock-free list of waiting tasks.
+///
+/// Cancelled waiters stay in the list until a notifier skips over them.
+struct WaitList {
+    waiters: UnboundedQueue<Arc<Waiter>>,
+    /// The number of waiters in the list.
+    /// Notifiers skip the list, if this is 0.
+    len: AtomicUsize,
+}
+
+impl WaitList {
+    fn new() -> Self {
+        Self {
+            waiters: UnboundedQueue::new(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds a waiter for the task of `waker`.
+    /// The caller must re-check its condition afterwards.
+    fn register(&self, waker: &Waker) -> Arc<Waiter> {
+        let waiter = Arc::new(Waiter {
+            state: AtomicU8::new(WAITING),
+            waker: waker.clone(),
+        });
+        self.len.fetch_add(1, Ordering::SeqCst);
+        let pushed = self.waiters.push(waiter.clone());
+        debug_assert!(pushed.is_ok(), "wait lists are never closed");
+        // a notifier either observes the waiter or its operation is observed by the re-check
+        fence(Ordering::SeqCst);
+        waiter
+    }
+
+    fn notify_one(&self) {
+        fence(Ordering::SeqCst);
+        while self.len.load(Ordering::SeqCst) > 0 {
+            let Some(waiter) = self.waiters.pop() else {
+                return;
+            };
+            self.len.fetch_sub(1, Ordering::SeqCst);
+            if waiter.notify() {
+                return;
+            }
+        }
+    }
+
+    fn notify_all(&self) {
+        fence(Ordering::SeqCst);
+        // waiters registering from here on observe the operation themselves
+        for _ in 0..self.len.load(Ordering::SeqCst) {
+            let Some(waiter) = self.waiters.pop() else {
+                return;
+            };
+            self.len.fetch_sub(1, Ordering::SeqCst);
+            waiter.notify();
+        }
+    }
+}
+
+/// The registration of a pending operation in a `WaitList`.
+struct Registration {
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Registration {
+    const fn new() -> Self {
+        Self { waiter: None }
+    }
+
+    /// Polls `attempt`, keeping the task registered in `list` until it returns Some.
+    fn poll<R>(
+        &mut self,
+        list: &WaitList,
+        cx: &mut Context<'_>,
+        mut attempt: impl FnMut() -> Option<R>,
+    ) -> Poll<R> {
+        if let Some(res) = attempt() {
+            self.complete();
+            return Poll::Ready(res);
+        }
+
+        if let Some(waiter) = &self.waiter
+            && waiter.state.load(Ordering::Acquire) == WAITING
+            && waiter.waker.will_wake(cx.waker())
+        {
+            return Poll::Pending;
+        }
+
+        // a notification of the previous waiter has been consumed by the attempt above
+        self.complete();
+        self.waiter = Some(list.register(cx.waker()));
+        match attempt() {
+            Some(res) => {
+                self.complete();
+                Poll::Ready(res)
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    /// Deregisters after the operation has been attempted.
+    fn complete(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            waiter.cancel();
+        }
+    }
+
+    /// Deregisters an abandoned operation.
+    /// A notification it has received is passed on to the next waiter.
+    fn abandon(&mut self, list: &WaitList) {
+        if let Some(waiter) = self.waiter.take()
+            && !waiter.cancel()
+        {
+            list.notify_one();
+        }
+    }
+}
+
+/// The sending half of a channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    #[cfg(feature = "futures")]
+    sink: SinkState<T>,
+}
+
+impl<T> Sender<T> {
+    /// Sends an item, waiting while the channel is full.
+    /// Resolves to the item as an error if all receivers have been dropped.
+    pub fn send(&self, item: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            item: Some(item),
+            registration: Registration::new(),
+        }
+    }
+
+    /// Attempts to send an item without waiting.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        if self.is_closed() {
+            return Err(TrySendError::Disconnected(item));
+        }
+        self.shared.try_send(item)
+    }
+
+    /// Sends the item in `slot` like `Shared::send_from`, failing if this sender has been closed.
+    fn send_from(&self, slot: &mut Option<T>) -> Option<Result<(), SendError<T>>> {
+        if self.is_closed() {
+            return Some(Err(SendError(slot.take().expect("item has been sent"))));
+        }
+        self.shared.send_from(slot)
+    }
+
+    /// Indicates whether this sender has been closed through `Sink::poll_close`,
+    /// after which it does not count as a sender anymore.
+    fn is_closed(&self) -> bool {
+        #[cfg(feature = "futures")]
+        return self.sink.closed;
+        #[cfg(not(feature = "futures"))]
+        false
+    }
+
+    /// Gives up this sender's share of the channel, disconnecting the receivers if it was the last one.
+    fn release(&self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // close the queue and wake all receivers, so they observe the disconnect
+            self.shared.queue.close();
+            self.shared.not_empty.notify_all();
+        }
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// Returns the number of items in the channel.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Indicates whether the channel is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+/// The clone of a closed sender is closed as well.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        if !self.is_closed() {
+            self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        }
+        Self {
+            shared: self.shared.clone(),
+            #[cfg(feature = "futures")]
+            sink: SinkState {
+                item: None,
+                registration: Registration::new(),
+                closed: self.is_closed(),
+            },
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "futures")]
+        self.sink.registration.abandon(&self.shared.not_full);
+        if !self.is_closed() {
+            self.release();
+        }
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Sender { ... }")
+    }
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    #[cfg(feature = "futures")]
+    stream: Registration,
+}
+
+impl<T> Receiver<T> {
+    /// Receives an item, waiting while the channel is empty.
+    /// Resolves to an error once the channel is empty and all senders have been dropped or closed.
+    pub fn recv(&self) -> RecvFuture<'_, T> {
+        RecvFuture {
+            receiver: self,
+            registration: Registration::new(),
+        }
+    }
+
+    /// Attempts to receive an item without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.shared.try_recv()
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// Returns the number of items in the channel.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Indicates whether the channel is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+            #[cfg(feature = "futures")]
+            stream: Registration::new(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "futures")]
+        self.stream.abandon(&self.shared.not_empty);
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // close the queue and wake all senders, so they observe the disconnect
+            self.shared.queue.close();
+            self.shared.not_full.notify_all();
+        }
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Receiver { ... }")
+    }
+}
+
+/// Future returned by `Sender::send`.
+#[must_use = "futures do nothing unless polled"]
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    item: Option<T>,
+    registration: Registration,
+}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let sender = this.sender;
+        let item = &mut this.item;
+        this.registration
+            .poll(&sender.shared.not_full, cx, || sender.send_from(item))
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        self.registration.abandon(&self.sender.shared.not_full);
+    }
+}
+
+impl<T> Debug for SendFuture<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("SendFuture { ... }")
+    }
+}
+
+/// The item is never pinned.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+/// Future returned by `Receiver::recv`.
+#[must_use = "futures do nothing unless polled"]
+pub struct RecvFuture<'a, T> {
+    receiver: &'a Receiver<T>,
+    registration: Registration,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let shared = &this.receiver.shared;
+        this.registration
+            .poll(&shared.not_empty, cx, || shared.recv())
+    }
+}
+
+impl<T> Drop for RecvFuture<'_, T> {
+    fn drop(&mut self) {
+        self.registration.abandon(&self.receiver.shared.not_empty);
+    }
+}
+
+impl<T> Debug for RecvFuture<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("RecvFuture { ... }")
+    }
+}
+
+/// The item buffered by `Sink::start_send`, while the channel is full.
+#[cfg(feature = "futures")]
+struct SinkState<T> {
+    item: Option<T>,
+    registration: Registration,
+    /// Set by `Sink::poll_close`, once the sender has given up its share of the channel
+    closed: bool,
+}
+
+#[cfg(feature = "futures")]
+mod futures_impls {
+    use super::*;
+    use core::task::ready;
+    use futures_core::Stream;
+    use futures_sink::Sink;
+
+    impl<T> Sender<T> {
+        /// Sends the buffered item, if there is one.
+        fn poll_sink(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>> {
+            let Self { shared, sink } = self;
+            if sink.item.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+            let item = &mut sink.item;
+            sink.registration
+                .poll(&shared.not_full, cx, || shared.send_from(item))
+        }
+    }
+
+    /// The buffered item is never pinned.
+    impl<T> Unpin for Sender<T> {}
+
+    /// Safety: the buffered item is only accessed through `&mut Sender`,
+    /// so sharing a Sender does not share T.
+    unsafe impl<T: Send> Sync for Sender<T> {}
+
+    impl<T> Sink<T> for Sender<T> {
+        type Error = SendError<T>;
+
+        fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.get_mut().poll_sink(cx)
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+            let this = self.get_mut();
+            debug_assert!(this.sink.item.is_none(), "start_send without poll_ready");
+            if this.sink.closed {
+                return Err(SendError(item));
+            }
+            // send right away if possible, the item is buffered otherwise
+            this.sink.item = Some(item);
+            if let Some(Err(err)) = this.shared.send_from(&mut this.sink.item) {
+                return Err(err);
+            }
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.get_mut().poll_sink(cx)
+        }
+
+        /// Sends the buffered item, then closes this sender, which stops counting as a sender like a dropped one.
+        /// Once all senders have been closed or dropped, the receivers observe the disconnect.
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            let this = self.get_mut();
+            if !this.sink.closed {
+                ready!(this.poll_sink(cx))?;
+                this.sink.closed = true;
+                this.release();
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<T> Stream for Receiver<T> {
+        type Item = T;
+
+        /// Ends once the channel is empty and all senders have been dropped or closed.
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+            let this = self.get_mut();
+            let shared = &this.shared;
+            let res = ready!(this.stream.poll(&shared.not_empty, cx, || shared.recv()));
+            Poll::Ready(res.ok())
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Strategies to wait between the retries of a contended operation.
+//!
+//! Every queue takes its strategy as a type parameter, which defaults to `Spin`.
+//! A fresh value of the strategy is created for each operation,
+//! and `wait` is called whenever a cell CAS fails or a slot is still busy, before the operation is retried.
+//!
+//! # Examples
+//!
+//! ```
+//! use nblfq::{Dense, HeaplessQueue, backoff::ExponentialSpin};
+//!
+//! static Q: HeaplessQueue<2, i32, Dense, ExponentialSpin> = HeaplessQueue::with_layout();
+//!
+//! assert_eq!(Q.push(&10), Ok(()));
+//! assert_eq!(Q.pop(), Some(&10));
+//! ```
+
+use core::hint::spin_loop;
+
+/// A strategy to wait between the retries of a contended operation.
+///
+/// The value is created with `Default` at the start of an operation, so it can keep track of the number of retries.
+pub trait Backoff: Default {
+    /// Called after a failed attempt, before the operation is retried.
+    fn wait(&mut self);
+}
+
+/// The number of doublings, after which `ExponentialSpin` stops growing, 1024 spins.
+const SPIN_LIMIT: u32 = 10;
+
+/// The number of doublings, after which `SpinThenYield` and `SpinThenSleep` stop spinning, 64 spins.
+#[cfg(feature = "std")]
+const YIELD_AFTER: u32 = 6;
+
+/// The number of yields `SpinThenSleep` does, before it puts the thread to sleep.
+#[cfg(feature = "std")]
+const SLEEP_AFTER: u32 = YIELD_AFTER + 4;
+
+/// Retries immediately.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    #[inline]
+    fn wait(&mut self) {}
+}
+
+/// Emits a single spin loop hint per retry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Spin;
+
+impl Backoff for Spin {
+    #[inline]
+    fn wait(&mut self) {
+        spin_loop();
+    }
+}
+
+/// Spins for a doubling number of iterations per retry, up to 1024.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExponentialSpin {
+    step: u32,
+}
+
+impl Backoff for ExponentialSpin {
+    #[inline]
+    fn wait(&mut self) {
+        for _ in 0..1 << self.step {
+            spin_loop();
+        }
+        if self.step < SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+}
+
+/// Spins exponentially for a few retries, then yields the thread to the scheduler.
+///
+/// This suits oversubscribed systems, where the thread blocking a cell may not be running.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinThenYield {
+    step: u32,
+}
+
+#[cfg(feature = "std")]
+impl Backoff for SpinThenYield {
+    fn wait(&mut self) {
+        if self.step < YIELD_AFTER {
+            for _ in 0..1 << self.step {
+                spin_loop();
+            }
+            self.step += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Spins exponentially, then yields, then puts the thread to sleep for a doubling duration of up to 1ms.
+///
+/// No thread wakes a sleeping one, so a retry is delayed by the full duration.
+/// The thread is not parked through `std::thread::park`, which would consume an unpark meant for its owner.
+/// To block until the opposite operation wakes the thread, use the queues of `channel`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinThenSleep {
+    step: u32,
+}
+
+#[cfg(feature = "std")]
+impl Backoff for SpinThenSleep {
+    fn wait(&mut self) {
+        if self.step < YIELD_AFTER {
+            for _ in 0..1 << self.step {
+                spin_loop();
+            }
+        } else if self.step < SLEEP_AFTER {
+            std::thread::yield_now();
+        } else {
+            let micros = 1 << (self.step - SLEEP_AFTER).min(SPIN_LIMIT);
+            std::thread::sleep(core::time::Duration::from_micros(micros));
+        }
+        self.step = self.step.saturating_add(1);
+    }
+}
@@ -0,0 +1,291 @@
+//! Blocking MPMC channels backed by a `HeapBackedQueue`.
+//!
+//! Senders block while the queue is full and receivers block while it is empty.
+//! Blocked threads are parked and woken by the opposite operation.
+//!
+//! # Examples
+//!
+//! ```
+//! use nblfq::channel;
+//! use std::thread;
+//!
+//! let (tx, rx) = channel::bounded(2);
+//!
+//! let handle = thread::spawn(move || {
+//!     for i in 0..10 {
+//!         tx.send(i).unwrap();
+//!     }
+//! });
+//!
+//! for i in 0..10 {
+//!     assert_eq!(rx.recv(), Ok(i));
+//! }
+//! handle.join().unwrap();
+//!
+//! // all senders have been dropped
+//! assert!(rx.recv().is_err());
+//! ```
+
+use core::{
+    fmt::{self, Debug},
+    sync::atomic::{AtomicUsize, Ordering, fence},
+};
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::HeapBackedQueue;
+pub use crate::error::{RecvError, SendError, TryRecvError, TrySendError};
+
+/// Creates a channel of capacity `cap`.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0.
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: HeapBackedQueue::new(cap),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        not_empty: Waiters::new(),
+        not_full: Waiters::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    queue: HeapBackedQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    /// Receivers waiting for an item
+    not_empty: Waiters,
+    /// Senders waiting for a free slot
+    not_full: Waiters,
+}
+
+/// A set of parked threads, waiting for a condition on the queue.
+struct Waiters {
+    lock: Mutex<()>,
+    cond: Condvar,
+    /// The number of threads currently parked (or about to park).
+    /// Notifiers skip the lock, if this is 0.
+    waiting: AtomicUsize,
+}
+
+impl Waiters {
+    fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            cond: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Calls `attempt` until it returns Some, parking the thread in between.
+    fn wait_until<R>(&self, mut attempt: impl FnMut() -> Option<R>) -> R {
+        loop {
+            if let Some(res) = attempt() {
+                return res;
+            }
+
+            let guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.waiting.fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            // a notifier either observes `waiting` or its operation is observed here
+            let res = attempt();
+            if res.is_none() {
+                drop(self.cond.wait(guard).unwrap_or_else(|e| e.into_inner()));
+            }
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+
+            if let Some(res) = res {
+                return res;
+            }
+        }
+    }
+
+    fn notify_one(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.cond.notify_one();
+        }
+    }
+
+    fn notify_all(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.cond.notify_all();
+        }
+    }
+}
+
+/// The sending half of a channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends an item, blocking while the channel is full.
+    /// Returns the item as an error if all receivers have been dropped.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let mut item = Some(item);
+        self.shared.not_full.wait_until(|| {
+            match self.push(item.take().expect("item has been sent")) {
+                Ok(()) => Some(Ok(())),
+                Err(TrySendError::Disconnected(it)) => Some(Err(SendError(it))),
+                Err(TrySendError::Full(it)) => {
+                    item = Some(it);
+                    None
+                }
+            }
+        })?;
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Attempts to send an item without blocking.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.push(item)?;
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Pushes an item without waking receivers.
+    /// This is called while holding the lock of `not_full`, so it must not notify.
+    fn push(&self, item: T) -> Result<(), TrySendError<T>> {
+        // the queue is closed once all receivers have been dropped
+        Ok(self.shared.queue.push(item)?)
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// Returns the number of items in the channel.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Indicates whether the channel is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // close the queue and wake all receivers, so they observe the disconnect
+            self.shared.queue.close();
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Sender { ... }")
+    }
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives an item, blocking while the channel is empty.
+    /// Returns an error once the channel is empty and all senders have been dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let item = self.shared.not_empty.wait_until(|| match self.pop() {
+            Ok(item) => Some(Ok(item)),
+            Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        })?;
+        self.shared.not_full.notify_one();
+        Ok(item)
+    }
+
+    /// Attempts to receive an item without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let item = self.pop()?;
+        self.shared.not_full.notify_one();
+        Ok(item)
+    }
+
+    /// Pops an item without waking senders.
+    /// This is called while holding the lock of `not_empty`, so it must not notify.
+    fn pop(&self) -> Result<T, TryRecvError> {
+        // the queue is closed once all senders have been dropped
+        Ok(self.shared.queue.try_pop()?)
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// Returns the number of items in the channel.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Indicates whether the channel is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // close the queue and wake all senders, so they observe the disconnect
+            self.shared.queue.close();
+            self.shared.not_full.notify_all();
+        }
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Receiver { ... }")
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = impl Iterator<Item = Self::Item>;
+
+    /// Returns a blocking iterator, which ends once the channel is disconnected.
+    fn into_iter(self) -> Self::IntoIter {
+        core::iter::from_fn(move || self.recv().ok())
+    }
+}
@@ -1,10 +1,9 @@
-use ::core::{
-    array,
-    sync::atomic::{AtomicU64, Ordering},
-};
+use ::core::sync::atomic::Ordering;
 use cfg_if::cfg_if;
 use core::marker::PhantomData;
 
+use crate::utils::prev;
+
 cfg_if! {
     if #[cfg(feature = "alloc")] {
         pub(crate) use heapless::*;
@@ -14,24 +13,198 @@ cfg_if! {
     }
 }
 
+/// A cell storing a (count, ptr) pair in the encoding of `S`.
+pub(crate) type Item<T, S = DefaultStorage> = GenericItem<T, <S as storage::Sealed>::Inner<T>>;
+
+pub(super) trait Buffer<T> {
+    /// The encoding of the cells
+    type Storage: Storage;
+
+    fn len(&self) -> usize;
+    fn cell(&self, idx: usize) -> &Item<T, Self::Storage>;
+    /// `len - 1`, if len is a power of two.
+    /// Indices are wrapped with this mask instead of a division.
+    fn mask(&self) -> Option<usize>;
+
+    /// Returns the index following `idx`
+    #[inline]
+    fn next(&self, idx: usize) -> usize {
+        match self.mask() {
+            Some(mask) => (idx + 1) & mask,
+            None => (idx + 1) % self.len(),
+        }
+    }
+
+    /// Returns the index preceding `idx`
+    #[inline]
+    fn prev(&self, idx: usize) -> usize {
+        match self.mask() {
+            Some(mask) => idx.wrapping_sub(1) & mask,
+            None => prev(idx, self.len()),
+        }
+    }
+}
+
+/// Returns the index mask of a buffer of length `len`.
+const fn mask_for(len: usize) -> Option<usize> {
+    if len.is_power_of_two() {
+        Some(len - 1)
+    } else {
+        None
+    }
+}
+
+/// The memory layout of the cells of a queue.
+///
+/// Neighbouring cells are claimed by different threads in quick succession.
+/// If they share a cache line, every CAS on one cell invalidates the line for the threads working on the others.
+/// This trait is sealed, it is implemented by `Dense` and `Padded`.
+pub trait CellLayout: layout::Sealed {}
+
+/// Cells are packed next to each other.
+///
+/// This is the smallest layout, several cells share a cache line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dense;
+
+/// Every cell occupies a cache line of its own.
+///
+/// This avoids false sharing between neighbouring cells under contention,
+/// at the cost of a cache line per cell.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Padded;
+
+impl CellLayout for Dense {}
+impl CellLayout for Padded {}
+
+mod layout {
+    use crate::utils::CachePadded;
+
+    pub trait Sealed {
+        /// A zero sized field, which sets the alignment of a cell
+        type Align;
+        const ALIGN: Self::Align;
+    }
+
+    impl Sealed for super::Dense {
+        type Align = ();
+        const ALIGN: Self::Align = ();
+    }
+
+    impl Sealed for super::Padded {
+        type Align = CachePadded<()>;
+        const ALIGN: Self::Align = CachePadded::new(());
+    }
+}
+
+/// The encoding of the (count, ptr) pair of each cell.
+///
+/// The round count distinguishes the laps of the ring, the number of its bits bounds how far
+/// a preempted operation may fall behind before it misorders cells.
+/// This trait is sealed, it is implemented by `Tagged`, `AlignTagged` and `DWord`.
+/// `DefaultStorage` is the choice for the target platform.
+pub trait Storage: storage::Sealed {}
+
+/// The pointer and a 16-bit round count packed into an `AtomicU64`.
+///
+/// With the `la57` feature, the pointer takes 57 bits and the count 7 bits.
+/// This is the default on 64-bit targets.
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tagged;
+
+/// Like `Tagged`, but the low bits of the round count are stored in the alignment bits of the pointer.
+///
+/// The count is widened by `log2(align_of::<T>())` bits, e.g. 19 bits for a `T` aligned to 8 bytes.
+#[cfg(target_pointer_width = "64")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlignTagged;
+
+/// The pointer next to a round count of the same width, i.e. a double word.
+///
+/// This is an `AtomicU128` on 64-bit targets, which keeps a 63-bit count,
+/// and an `AtomicU64` with a 32-bit count on 32-bit targets, where it is the default.
+/// Targets without native atomics of that size fall back to the spinlocks of `portable-atomic`,
+/// a queue using this storage is not lock-free there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DWord;
+
+/// A 32-bit index in place of the pointer next to a 32-bit round count, packed into an `AtomicU64`.
+///
+/// The index is stored as a pointer without provenance, which is never dereferenced.
+/// This is only used by `IndexQueue`.
+#[cfg_attr(not(feature = "alloc"), allow(unused))]
+pub struct Index;
+
 cfg_if! {
-    if #[cfg(not(feature = "no-tagged-ptr"))] {
-        use tagged_ptr::*;
-        pub(crate) type PtrType<T> = TaggedItemInner<T>;
+    if #[cfg(target_pointer_width = "32")] {
+        /// The storage used by queues, which do not name one.
+        pub type DefaultStorage = DWord;
     } else {
-        use dword_item_portable::*;
-        pub(crate) type PtrType<T> = DWordItemInner<T>;
+        /// The storage used by queues, which do not name one.
+        pub type DefaultStorage = Tagged;
     }
 }
 
-pub(crate) type Item<T> = GenericItem<T, PtrType<T>>;
+#[cfg(target_pointer_width = "64")]
+impl Storage for Tagged {}
+#[cfg(target_pointer_width = "64")]
+impl Storage for AlignTagged {}
+impl Storage for DWord {}
+impl Storage for Index {}
 
-pub(super) trait Buffer<T> {
-    fn len(&self) -> usize;
-    fn inner(&self) -> &[Item<T>];
+mod storage {
+    use super::*;
+
+    pub trait Sealed {
+        type Inner<T>: ItemInner<T>;
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    impl Sealed for Tagged {
+        type Inner<T> = tagged_ptr::TaggedItemInner<T>;
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    impl Sealed for AlignTagged {
+        type Inner<T> = aligned_tagged_ptr::AlignedTaggedItemInner<T>;
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    impl Sealed for DWord {
+        type Inner<T> = dword_item_portable::DWordItemInner<T>;
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    impl Sealed for DWord {
+        type Inner<T> = dword32::DWord32ItemInner<T>;
+    }
+
+    impl Sealed for Index {
+        type Inner<T> = dword32::DWord32ItemInner<T>;
+    }
 }
 
-pub(crate) trait ItemInner<T> {
+/// An Item<T> laid out according to `L` and encoded according to `S`.
+pub(crate) struct Cell<T, L: CellLayout, S: Storage = DefaultStorage> {
+    item: Item<T, S>,
+    _align: L::Align,
+}
+
+impl<T, L: CellLayout, S: Storage> Cell<T, L, S> {
+    const fn new() -> Self {
+        Self {
+            item: Item::<T, S>::new(),
+            _align: L::ALIGN,
+        }
+    }
+}
+
+/// The atomic word of a cell, see `Storage`.
+///
+/// This is public, but unnameable, as it bounds `Inner` of the sealed `Storage` trait.
+pub trait ItemInner<T> {
+    /// The number of distinct round counts
     const MAX_W: u64;
     /// returns (count, ptr)
     fn components(&self) -> (u64, *const T);
@@ -43,37 +216,51 @@ pub(crate) trait ItemInner<T> {
         new_ptr: *const T,
         new_count: u64,
     ) -> Result<(u64, *const T), (u64, *const T)>;
-    fn new() -> Self;
+    /// stores count + ptr, only the single thread able to write the cell may do so
+    fn store(&self, ptr: *const T, count: u64);
+    /// an empty item with count 0
+    const EMPTY: Self;
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    fn from_components(count: u64, ptr: *const T) -> Self;
 }
 
 mod heapless {
     use super::*;
 
-    pub struct HeaplessBuf<const N: usize, T> {
-        inner: [Item<T>; N],
+    pub struct HeaplessBuf<const N: usize, T, L: CellLayout = Dense, S: Storage = DefaultStorage> {
+        inner: [Cell<T, L, S>; N],
     }
 
-    impl<const N: usize, T> HeaplessBuf<N, T> {
-        pub fn new() -> Self {
+    impl<const N: usize, T, L: CellLayout, S: Storage> HeaplessBuf<N, T, L, S> {
+        pub const fn new() -> Self {
             Self {
-                inner: array::from_fn(|_| Item::new()),
+                inner: [const { Cell::new() }; N],
             }
         }
     }
 
-    impl<const N: usize, T> Default for HeaplessBuf<N, T> {
+    impl<const N: usize, T, L: CellLayout, S: Storage> Default for HeaplessBuf<N, T, L, S> {
         fn default() -> Self {
             Self::new()
         }
     }
 
-    impl<const N: usize, T> Buffer<T> for HeaplessBuf<N, T> {
+    impl<const N: usize, T, L: CellLayout, S: Storage> Buffer<T> for HeaplessBuf<N, T, L, S> {
+        type Storage = S;
+
         fn len(&self) -> usize {
             N
         }
 
-        fn inner(&self) -> &[Item<T>] {
-            &self.inner
+        #[inline]
+        fn cell(&self, idx: usize) -> &Item<T, S> {
+            &self.inner[idx].item
+        }
+
+        #[inline]
+        fn mask(&self) -> Option<usize> {
+            // evaluated at compile time, a power of two N never divides
+            const { mask_for(N) }
         }
     }
 }
@@ -81,27 +268,111 @@ mod heapless {
 #[cfg(feature = "alloc")]
 mod heap_based {
     use super::*;
-    use alloc::boxed::Box;
-
-    pub struct FixedBuf<T> {
-        inner: Box<[Item<T>]>,
+    use alloc::{
+        alloc::{Allocator, Global},
+        boxed::Box,
+        vec::Vec,
+    };
+    use core::{cell::UnsafeCell, mem::MaybeUninit};
+
+    pub struct FixedBuf<
+        T,
+        A: Allocator = Global,
+        L: CellLayout = Dense,
+        S: Storage = DefaultStorage,
+    > {
+        inner: Box<[Cell<T, L, S>], A>,
+        mask: Option<usize>,
     }
 
     impl<T> FixedBuf<T> {
+        pub fn new(size: usize) -> Self {
+            Self::new_in(size, Global)
+        }
+    }
+
+    impl<T, A: Allocator, L: CellLayout, S: Storage> FixedBuf<T, A, L, S> {
+        pub fn new_in(size: usize, alloc: A) -> Self {
+            let mut inner = Vec::with_capacity_in(size, alloc);
+            inner.extend((0..size).map(|_| Cell::new()));
+            Self {
+                inner: inner.into_boxed_slice(),
+                mask: mask_for(size),
+            }
+        }
+    }
+
+    impl<T, A: Allocator, L: CellLayout, S: Storage> Buffer<T> for FixedBuf<T, A, L, S> {
+        type Storage = S;
+
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        #[inline]
+        fn cell(&self, idx: usize) -> &Item<T, S> {
+            &self.inner[idx].item
+        }
+
+        #[inline]
+        fn mask(&self) -> Option<usize> {
+            self.mask
+        }
+    }
+
+    /// A heap allocated buffer, which stores its values in place.
+    ///
+    /// The Item<T>'s only hold the state and round counter of their slot,
+    /// the value itself lives in the slot with the same index.
+    pub struct InlineBuf<T, L: CellLayout = Dense> {
+        inner: FixedBuf<T, Global, L>,
+        values: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    }
+
+    impl<T, L: CellLayout> InlineBuf<T, L> {
         pub fn new(size: usize) -> Self {
             Self {
-                inner: (0..size).map(|_| Item::new()).collect(),
+                inner: FixedBuf::new_in(size, Global),
+                values: (0..size)
+                    .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                    .collect(),
             }
         }
+
+        /// Writes a value into slot `idx`.
+        ///
+        /// # Safety
+        ///
+        /// The caller must have exclusive access to the slot and the slot must not hold a value.
+        pub unsafe fn write(&self, idx: usize, value: T) {
+            unsafe { (*self.values[idx].get()).write(value) };
+        }
+
+        /// Moves the value out of slot `idx`.
+        ///
+        /// # Safety
+        ///
+        /// The caller must have exclusive access to the slot and the slot must hold a value.
+        pub unsafe fn read(&self, idx: usize) -> T {
+            unsafe { (*self.values[idx].get()).assume_init_read() }
+        }
     }
 
-    impl<T> Buffer<T> for FixedBuf<T> {
+    impl<T, L: CellLayout> Buffer<T> for InlineBuf<T, L> {
+        type Storage = DefaultStorage;
+
         fn len(&self) -> usize {
             self.inner.len()
         }
 
-        fn inner(&self) -> &[Item<T>] {
-            &self.inner
+        #[inline]
+        fn cell(&self, idx: usize) -> &Item<T> {
+            self.inner.cell(idx)
+        }
+
+        #[inline]
+        fn mask(&self) -> Option<usize> {
+            self.inner.mask()
         }
     }
 }
@@ -112,9 +383,20 @@ pub(crate) struct GenericItem<T, I: ItemInner<T>> {
 }
 
 impl<T, I: ItemInner<T>> GenericItem<T, I> {
-    fn new() -> Self {
+    /// The number of distinct round counts
+    pub(crate) const MAX_W: u64 = I::MAX_W;
+
+    const fn new() -> Self {
         Self {
-            inner: I::new(),
+            inner: I::EMPTY,
+            _data: PhantomData,
+        }
+    }
+
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn from_components(count: u64, ptr: *const T) -> Self {
+        Self {
+            inner: I::from_components(count, ptr),
             _data: PhantomData,
         }
     }
@@ -134,44 +416,45 @@ impl<T, I: ItemInner<T>> GenericItem<T, I> {
     ) -> Result<(u64, *const T), (u64, *const T)> {
         self.inner.cmpxchg(old_ptr, old_count, new_ptr, new_count)
     }
+
+    #[inline]
+    #[cfg_attr(not(feature = "alloc"), allow(unused))]
+    pub(crate) fn store(&self, ptr: *const T, count: u64) {
+        self.inner.store(ptr, count)
+    }
 }
 
-#[cfg(not(feature = "no-tagged-ptr"))]
+#[cfg(target_pointer_width = "64")]
 mod tagged_ptr {
     use super::*;
-    use crate::utils::{components_as_tagged, components_from_tagged};
+    use crate::utils::{COUNT_RANGE, components_as_tagged, components_from_tagged};
+    use ::core::sync::atomic::AtomicU64;
 
-    pub(crate) struct TaggedItemInner<T> {
-        // the pointer part takes up the first 48 bits, count the last 16
+    pub struct TaggedItemInner<T> {
+        // the pointer part takes up the low PTR_BITS bits, count the remaining high bits
         ptr: AtomicU64,
         _data: PhantomData<*const T>,
     }
 
-    #[allow(unused)]
     impl<T> TaggedItemInner<T> {
-        pub fn from_tagged(ptr: u64) -> Self {
+        pub const fn from_tagged(ptr: u64) -> Self {
             Self {
                 ptr: AtomicU64::new(ptr),
                 _data: PhantomData,
             }
         }
-
-        pub fn from_components(count: u64, ptr: *const T) -> Self {
-            Self::from_tagged(components_as_tagged(count, ptr))
-        }
     }
 
     impl<T> ItemInner<T> for TaggedItemInner<T> {
-        const MAX_W: u64 = u16::MAX as u64 + 1;
+        const MAX_W: u64 = COUNT_RANGE;
+        const EMPTY: Self = Self::from_tagged(0);
+
         fn components(&self) -> (u64, *const T) {
             components_from_tagged(self.ptr.load(Ordering::Acquire))
         }
 
-        fn new() -> Self {
-            Self {
-                ptr: AtomicU64::new(0),
-                _data: PhantomData,
-            }
+        fn from_components(count: u64, ptr: *const T) -> Self {
+            Self::from_tagged(components_as_tagged(count, ptr))
         }
 
         fn cmpxchg(
@@ -188,26 +471,82 @@ mod tagged_ptr {
                 .map(|p| components_from_tagged(p))
                 .map_err(|p| components_from_tagged(p))
         }
+
+        fn store(&self, ptr: *const T, count: u64) {
+            self.ptr
+                .store(components_as_tagged(count, ptr), Ordering::Release);
+        }
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+mod aligned_tagged_ptr {
+    use super::*;
+    use crate::utils::{COUNT_RANGE, align_bits, components_as_aligned, components_from_aligned};
+    use ::core::sync::atomic::AtomicU64;
+
+    pub struct AlignedTaggedItemInner<T> {
+        // like TaggedItemInner, but the low bits of the count are stored in the alignment bits of the pointer
+        ptr: AtomicU64,
+        _data: PhantomData<*const T>,
+    }
+
+    impl<T> AlignedTaggedItemInner<T> {
+        pub const fn from_tagged(ptr: u64) -> Self {
+            Self {
+                ptr: AtomicU64::new(ptr),
+                _data: PhantomData,
+            }
+        }
+    }
+
+    impl<T> ItemInner<T> for AlignedTaggedItemInner<T> {
+        const MAX_W: u64 = COUNT_RANGE << align_bits::<T>();
+        const EMPTY: Self = Self::from_tagged(0);
+
+        fn components(&self) -> (u64, *const T) {
+            components_from_aligned(self.ptr.load(Ordering::Acquire))
+        }
+
+        fn from_components(count: u64, ptr: *const T) -> Self {
+            Self::from_tagged(components_as_aligned(count, ptr))
+        }
+
+        fn cmpxchg(
+            &self,
+            old_ptr: *const T,
+            old_count: u64,
+            new_ptr: *const T,
+            new_count: u64,
+        ) -> Result<(u64, *const T), (u64, *const T)> {
+            let old = components_as_aligned(old_count, old_ptr);
+            let new = components_as_aligned(new_count, new_ptr);
+            self.ptr
+                .compare_exchange(old, new, Ordering::AcqRel, Ordering::Relaxed)
+                .map(|p| components_from_aligned(p))
+                .map_err(|p| components_from_aligned(p))
+        }
+
+        fn store(&self, ptr: *const T, count: u64) {
+            self.ptr
+                .store(components_as_aligned(count, ptr), Ordering::Release);
+        }
     }
 }
 
-#[cfg(feature = "no-tagged-ptr")]
+#[cfg(target_pointer_width = "64")]
 mod dword_item_portable {
     use super::*;
     use crate::utils::{components_as_dword, components_from_dword};
     use portable_atomic::AtomicU128;
 
-    pub(crate) struct DWordItemInner<T> {
+    pub struct DWordItemInner<T> {
         storage: AtomicU128,
         _data: PhantomData<*const T>,
     }
 
     impl<T> DWordItemInner<T> {
-        pub(crate) fn from_components(count: u64, ptr: *const T) -> Self {
-            Self::from_dword(components_as_dword(count, ptr))
-        }
-
-        pub(crate) fn from_dword(dword: u128) -> Self {
+        pub(crate) const fn from_dword(dword: u128) -> Self {
             Self {
                 storage: AtomicU128::new(dword),
                 _data: PhantomData,
@@ -216,11 +555,18 @@ mod dword_item_portable {
     }
 
     impl<T> ItemInner<T> for DWordItemInner<T> {
-        const MAX_W: u64 = u64::MAX;
+        // the top bit is left free, so that `count + MAX_W - 1` does not overflow
+        const MAX_W: u64 = 1 << 63;
+        const EMPTY: Self = Self::from_dword(0);
+
         fn components(&self) -> (u64, *const T) {
             components_from_dword(self.storage.load(Ordering::Acquire))
         }
 
+        fn from_components(count: u64, ptr: *const T) -> Self {
+            Self::from_dword(components_as_dword(count, ptr))
+        }
+
         fn cmpxchg(
             &self,
             old_ptr: *const T,
@@ -236,8 +582,64 @@ mod dword_item_portable {
                 .map_err(|dword| components_from_dword(dword))
         }
 
-        fn new() -> Self {
-            Self::from_dword(0)
+        fn store(&self, ptr: *const T, count: u64) {
+            self.storage
+                .store(components_as_dword(count, ptr), Ordering::Release);
+        }
+    }
+}
+
+mod dword32 {
+    use super::*;
+    use crate::utils::{components_as_dword32, components_from_dword32};
+    // native on targets with 64-bit atomics, emulated otherwise
+    use portable_atomic::AtomicU64;
+
+    pub struct DWord32ItemInner<T> {
+        // the pointer takes up the low 32 bits, count the high 32 bits
+        storage: AtomicU64,
+        _data: PhantomData<*const T>,
+    }
+
+    impl<T> DWord32ItemInner<T> {
+        pub(crate) const fn from_dword(dword: u64) -> Self {
+            Self {
+                storage: AtomicU64::new(dword),
+                _data: PhantomData,
+            }
+        }
+    }
+
+    impl<T> ItemInner<T> for DWord32ItemInner<T> {
+        const MAX_W: u64 = 1 << 32;
+        const EMPTY: Self = Self::from_dword(0);
+
+        fn components(&self) -> (u64, *const T) {
+            components_from_dword32(self.storage.load(Ordering::Acquire))
+        }
+
+        fn from_components(count: u64, ptr: *const T) -> Self {
+            Self::from_dword(components_as_dword32(count, ptr))
+        }
+
+        fn cmpxchg(
+            &self,
+            old_ptr: *const T,
+            old_count: u64,
+            new_ptr: *const T,
+            new_count: u64,
+        ) -> Result<(u64, *const T), (u64, *const T)> {
+            let old = components_as_dword32(old_count, old_ptr);
+            let new = components_as_dword32(new_count, new_ptr);
+            self.storage
+                .compare_exchange(old, new, Ordering::AcqRel, Ordering::Relaxed)
+                .map(|dword| components_from_dword32(dword))
+                .map_err(|dword| components_from_dword32(dword))
+        }
+
+        fn store(&self, ptr: *const T, count: u64) {
+            self.storage
+                .store(components_as_dword32(count, ptr), Ordering::Release);
         }
     }
 }
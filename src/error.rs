@@ -0,0 +1,184 @@
+//! Errors returned by the queues and channels.
+
+use core::fmt::{self, Debug, Display};
+
+#[cfg(feature = "alloc")]
+pub use channel::*;
+
+/// Returned by `push`, if the item could not be pushed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The queue is full.
+    Full(T),
+    /// The queue has been closed.
+    Closed(T),
+}
+
+impl<T> PushError<T> {
+    /// Returns the item, which could not be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(item) | Self::Closed(item) => item,
+        }
+    }
+
+    /// Indicates whether the queue was full.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Indicates whether the queue was closed.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> PushError<U> {
+        match self {
+            Self::Full(item) => PushError::Full(f(item)),
+            Self::Closed(item) => PushError::Closed(f(item)),
+        }
+    }
+}
+
+impl<T> Debug for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.pad("Full(..)"),
+            Self::Closed(_) => f.pad("Closed(..)"),
+        }
+    }
+}
+
+impl<T> Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.pad("pushing into a full queue"),
+            Self::Closed(_) => f.pad("pushing into a closed queue"),
+        }
+    }
+}
+
+impl<T> core::error::Error for PushError<T> {}
+
+/// Returned by `try_pop`, if no item could be popped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopError {
+    /// The queue is empty.
+    Empty,
+    /// The queue has been closed and all of its items have been popped.
+    Closed,
+}
+
+impl Display for PopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.pad("popping from an empty queue"),
+            Self::Closed => f.pad("popping from a closed and drained queue"),
+        }
+    }
+}
+
+impl core::error::Error for PopError {}
+
+#[cfg(feature = "alloc")]
+mod channel {
+    use super::*;
+
+    /// Returned by `Sender::send`, if all receivers have been dropped.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct SendError<T>(pub T);
+
+    impl<T> Debug for SendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.pad("SendError { .. }")
+        }
+    }
+
+    impl<T> Display for SendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.pad("sending on a disconnected channel")
+        }
+    }
+
+    impl<T> core::error::Error for SendError<T> {}
+
+    /// Returned by `Sender::try_send`.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub enum TrySendError<T> {
+        /// The channel is full.
+        Full(T),
+        /// All receivers have been dropped.
+        Disconnected(T),
+    }
+
+    impl<T> Debug for TrySendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Full(_) => f.pad("Full(..)"),
+                Self::Disconnected(_) => f.pad("Disconnected(..)"),
+            }
+        }
+    }
+
+    impl<T> Display for TrySendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Full(_) => f.pad("sending on a full channel"),
+                Self::Disconnected(_) => f.pad("sending on a disconnected channel"),
+            }
+        }
+    }
+
+    impl<T> core::error::Error for TrySendError<T> {}
+
+    /// A closed queue means that one side of the channel has been dropped.
+    impl<T> From<PushError<T>> for TrySendError<T> {
+        fn from(err: PushError<T>) -> Self {
+            match err {
+                PushError::Full(item) => Self::Full(item),
+                PushError::Closed(item) => Self::Disconnected(item),
+            }
+        }
+    }
+
+    /// Returned by `Receiver::recv`, if the channel is empty and all senders have been dropped.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RecvError;
+
+    impl Display for RecvError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.pad("receiving on an empty and disconnected channel")
+        }
+    }
+
+    impl core::error::Error for RecvError {}
+
+    /// Returned by `Receiver::try_recv`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TryRecvError {
+        /// The channel is empty.
+        Empty,
+        /// The channel is empty and all senders have been dropped.
+        Disconnected,
+    }
+
+    impl Display for TryRecvError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Empty => f.pad("receiving on an empty channel"),
+                Self::Disconnected => f.pad("receiving on an empty and disconnected channel"),
+            }
+        }
+    }
+
+    impl core::error::Error for TryRecvError {}
+
+    impl From<PopError> for TryRecvError {
+        fn from(err: PopError) -> Self {
+            match err {
+                PopError::Empty => Self::Empty,
+                PopError::Closed => Self::Disconnected,
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use alloc::{
+    alloc::{Allocator, Global},
+    boxed::Box,
+};
+use core::{
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, fence},
+};
+
+use crate::{Backoff, utils::CachePadded};
+
+/// A hazard pointer, through which a reader announces the item it references in place.
+struct Record {
+    item: AtomicPtr<()>,
+    claimed: AtomicBool,
+    /// The next record of the list, which is never changed once the record has been published.
+    next: *mut Record,
+}
+
+/// The hazard pointers of a queue, which keep items referenced in place from being moved out by a pop.
+///
+/// A reader claims a record for the duration of its read and announces the item in it.
+/// The records are kept in a lock-free list, which only grows: if every record is claimed, a new one is allocated in `A`,
+/// so readers never wait for each other. A pop only waits for the readers, which announce the item it has taken.
+/// While no record is claimed, a pop does not walk the list at all.
+pub(crate) struct Hazards<A: Allocator = Global> {
+    head: AtomicPtr<Record>,
+    /// The number of claimed records
+    active: CachePadded<AtomicUsize>,
+    alloc: A,
+}
+
+impl<A: Allocator> Hazards<A> {
+    pub(crate) fn new_in(alloc: A) -> Self {
+        Self {
+            head: AtomicPtr::new(null_mut()),
+            active: CachePadded::new(AtomicUsize::new(0)),
+            alloc,
+        }
+    }
+
+    /// Claims an unused record, or allocates a new one if all of them are claimed.
+    pub(crate) fn claim(&self) -> Hazard<'_> {
+        // counted before any announcement, which `wait_for` has to observe
+        self.active.fetch_add(1, Ordering::SeqCst);
+        let mut current = self.head.load(Ordering::Acquire);
+        // Safety: records are only freed when the list is dropped
+        while let Some(record) = unsafe { current.as_ref() } {
+            if !record.claimed.load(Ordering::Relaxed)
+                && record
+                    .claimed
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Hazard {
+                    record,
+                    active: &self.active,
+                };
+            }
+            current = record.next;
+        }
+
+        let record = Box::into_raw_with_allocator(Box::new_in(
+            Record {
+                item: AtomicPtr::new(null_mut()),
+                claimed: AtomicBool::new(true),
+                next: null_mut(),
+            },
+            &self.alloc,
+        ))
+        .0;
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // Safety: the record is not published yet
+            unsafe { (*record).next = head };
+            match self.head.compare_exchange_weak(
+                head,
+                record,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        Hazard {
+            record: unsafe { &*record },
+            active: &self.active,
+        }
+    }
+
+    /// Waits until no reader announces `item` anymore.
+    ///
+    /// `item` must have been popped before, so a reader announcing it afterwards fails to validate it.
+    pub(crate) fn wait_for<B: Backoff>(&self, item: *const ()) {
+        // pairs with the fence in `Hazard::protect`,
+        // either the reader observes the pop, or its announcement is observed here
+        fence(Ordering::SeqCst);
+        if self.active.load(Ordering::Acquire) == 0 {
+            // a record claimed from now on announces the item only after the pop
+            return;
+        }
+        let mut current = self.head.load(Ordering::Acquire);
+        while let Some(record) = unsafe { current.as_ref() } {
+            let mut backoff = B::default();
+            while core::ptr::eq(record.item.load(Ordering::Acquire), item) {
+                backoff.wait();
+            }
+            current = record.next;
+        }
+    }
+}
+
+impl<A: Allocator> Drop for Hazards<A> {
+    fn drop(&mut self) {
+        let mut current = *self.head.get_mut();
+        while !current.is_null() {
+            // Safety: no record is claimed anymore, and each of them has been allocated in `alloc`
+            let record = unsafe { Box::from_raw_in(current, &self.alloc) };
+            current = record.next;
+        }
+    }
+}
+
+/// A claimed record, which is released on drop.
+pub(crate) struct Hazard<'a> {
+    record: &'a Record,
+    active: &'a AtomicUsize,
+}
+
+impl Hazard<'_> {
+    /// Announces `item` and returns true, if `valid` confirms afterwards that it has not been popped.
+    /// Otherwise the announcement is withdrawn.
+    pub(crate) fn protect(&self, item: *const (), valid: impl FnOnce() -> bool) -> bool {
+        self.record.item.store(item as *mut (), Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+        if valid() {
+            return true;
+        }
+        self.clear();
+        false
+    }
+
+    /// Withdraws the announcement, once the item is not referenced anymore.
+    pub(crate) fn clear(&self) {
+        self.record.item.store(null_mut(), Ordering::Release);
+    }
+}
+
+impl Drop for Hazard<'_> {
+    fn drop(&mut self) {
+        self.clear();
+        self.record.claimed.store(false, Ordering::Release);
+        self.active.fetch_sub(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::Spin;
+
+    #[test]
+    fn reuses_records() {
+        let hazards = Hazards::new_in(Global);
+        let item = core::ptr::dangling::<()>();
+
+        let first = hazards.claim();
+        let first_record = first.record as *const Record;
+        let second = hazards.claim();
+        assert_ne!(first_record, second.record as *const Record);
+
+        assert!(first.protect(item, || true));
+        assert!(!second.protect(item, || false));
+        drop(first);
+        // the withdrawn and the released announcement do not block a pop
+        hazards.wait_for::<Spin>(item);
+
+        let third = hazards.claim();
+        assert_eq!(first_record, third.record as *const Record);
+        assert_eq!(hazards.active.load(Ordering::Relaxed), 2);
+
+        drop(second);
+        drop(third);
+        assert_eq!(hazards.active.load(Ordering::Relaxed), 0);
+    }
+}
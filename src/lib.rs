@@ -1,5 +1,6 @@
 #![no_std]
 #![feature(impl_trait_in_assoc_type)]
+#![cfg_attr(feature = "alloc", feature(allocator_api))]
 
 #[cfg(any(feature = "alloc", test))]
 extern crate alloc;
@@ -8,9 +9,30 @@ extern crate alloc;
 extern crate std;
 
 mod arrayqueue;
+#[cfg(feature = "alloc")]
+pub mod async_channel;
+pub mod backoff;
+#[cfg(feature = "std")]
+pub mod channel;
 mod components;
+mod error;
+#[cfg(feature = "alloc")]
+mod hazard;
+#[cfg(feature = "alloc")]
+mod pool;
+#[cfg(feature = "alloc")]
+pub mod split;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "alloc")]
+mod unbounded;
 mod utils;
 
 pub use arrayqueue::*;
+pub use backoff::Backoff;
+#[cfg(target_pointer_width = "64")]
+pub use components::{AlignTagged, Tagged};
+pub use components::{CellLayout, DWord, DefaultStorage, Dense, Padded, Storage};
+pub use error::{PopError, PushError};
+#[cfg(feature = "alloc")]
+pub use unbounded::UnboundedQueue;
@@ -0,0 +1,111 @@
+use alloc::{
+    alloc::{Allocator, Global},
+    boxed::Box,
+};
+use core::mem::MaybeUninit;
+
+use crate::{arrayqueue::ArrayQueue, components::FixedBuf};
+
+/// A lock-free pool of boxes, used to recycle the item boxes of a queue.
+///
+/// The free boxes are themselves kept in an NBLFQ ring.
+/// If the pool runs dry, a new box is allocated; if it overflows, the box is freed.
+/// All boxes, as well as the ring itself, are allocated in `A`.
+pub(crate) struct BoxPool<T, A: Allocator = Global> {
+    free: ArrayQueue<MaybeUninit<T>, FixedBuf<MaybeUninit<T>, A>>,
+    alloc: A,
+}
+
+impl<T, A: Allocator + Clone> BoxPool<T, A> {
+    /// Creates a pool able to hold `capacity` boxes, `prealloc` of which are allocated up front.
+    pub(crate) fn new_in(prealloc: usize, capacity: usize, alloc: A) -> Self {
+        debug_assert!(prealloc <= capacity);
+        let pool = Self {
+            free: ArrayQueue::new_in(FixedBuf::new_in(capacity, alloc.clone())),
+            alloc,
+        };
+        for _ in 0..prealloc {
+            let (slot, _) = Box::into_raw_with_allocator(Box::new_uninit_in(&pool.alloc));
+            let pushed = pool.free.push(slot);
+            debug_assert!(pushed.is_ok(), "pool overflowed while preallocating");
+        }
+        pool
+    }
+}
+
+impl<T, A: Allocator> BoxPool<T, A> {
+    /// Moves `item` into a box taken from the pool.
+    /// A new box is allocated, if the pool is empty.
+    pub(crate) fn boxed(&self, item: T) -> *const T {
+        match self.free.pop() {
+            Some(slot) => {
+                let slot = slot as *mut MaybeUninit<T>;
+                // Safety: boxes in the pool are not shared
+                unsafe { (*slot).write(item) };
+                slot as *const T
+            }
+            None => Box::into_raw_with_allocator(Box::new_in(item, &self.alloc)).0,
+        }
+    }
+
+    /// Moves the item out of its box and returns the box to the pool.
+    ///
+    /// # Safety
+    ///
+    /// `item` must have been returned by `boxed` of this pool and must not be used afterwards.
+    pub(crate) unsafe fn unboxed(&self, item: *const T) -> T {
+        let slot = item as *mut MaybeUninit<T>;
+        // Safety: the box holds an initialized T, which is moved out exactly once
+        let item = unsafe { (*slot).assume_init_read() };
+        if let Err(err) = self.free.push(slot) {
+            // Safety: the box is not part of the pool, so it is owned here
+            let slot = err.into_inner() as *mut MaybeUninit<T>;
+            drop(unsafe { Box::from_raw_in(slot, &self.alloc) });
+        }
+        item
+    }
+}
+
+impl<T, A: Allocator> Drop for BoxPool<T, A> {
+    fn drop(&mut self) {
+        // free all pooled boxes
+        while let Some(slot) = self.free.pop() {
+            drop(unsafe { Box::from_raw_in(slot as *mut MaybeUninit<T>, &self.alloc) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycles_boxes() {
+        let pool = BoxPool::new_in(0, 1, Global);
+
+        let first = pool.boxed(1);
+        assert_eq!(unsafe { pool.unboxed(first) }, 1);
+
+        let second = pool.boxed(2);
+        assert_eq!(first, second);
+        assert_eq!(unsafe { pool.unboxed(second) }, 2);
+    }
+
+    #[test]
+    fn overflow() {
+        let pool = BoxPool::new_in(1, 1, Global);
+
+        // the preallocated box and a fresh one
+        let first = pool.boxed(1);
+        let second = pool.boxed(2);
+        assert_ne!(first, second);
+
+        assert_eq!(unsafe { pool.unboxed(first) }, 1);
+        // the pool is full, this box is freed
+        assert_eq!(unsafe { pool.unboxed(second) }, 2);
+
+        let third = pool.boxed(3);
+        assert_eq!(third, first);
+        assert_eq!(unsafe { pool.unboxed(third) }, 3);
+    }
+}
@@ -0,0 +1,334 @@
+//! Producer and consumer handles of a `HeapBackedQueue`.
+//!
+//! `HeapBackedQueue::split` moves the queue behind a pair of handles and fixes the role of each side.
+//! A `Multi` side can be cloned and pushes or pops with the usual cell CAS.
+//! A `Single` side cannot be cloned or shared between threads, so it owns its end of the queue:
+//! it remembers the cell it claims next, and claims it with a plain store instead of a CAS and without a scan of the buffer.
+//!
+//! # Examples
+//!
+//! ```
+//! use nblfq::HeapBackedQueue;
+//! use nblfq::split::{Multi, Single};
+//! use std::thread;
+//!
+//! let (tx, rx) = HeapBackedQueue::new(2).split::<Multi, Single>();
+//!
+//! let handles: Vec<_> = (0..2)
+//!     .map(|_| {
+//!         let tx = tx.clone();
+//!         thread::spawn(move || {
+//!             for i in 0..5 {
+//!                 while tx.push(i).is_err() {
+//!                     thread::yield_now();
+//!                 }
+//!             }
+//!         })
+//!     })
+//!     .collect();
+//!
+//! let mut sum = 0;
+//! for _ in 0..10 {
+//!     loop {
+//!         if let Some(i) = rx.pop() {
+//!             sum += i;
+//!             break;
+//!         }
+//!         thread::yield_now();
+//!     }
+//! }
+//! assert_eq!(sum, 20);
+//!
+//! for handle in handles {
+//!     handle.join().unwrap();
+//! }
+//! ```
+
+use alloc::{
+    alloc::{Allocator, Global},
+    sync::Arc,
+};
+use core::{
+    cell::Cell,
+    fmt::{self, Debug},
+};
+
+use crate::arrayqueue::Cursor;
+use crate::{
+    Backoff, CellLayout, DefaultStorage, Dense, HeapBackedQueue, PopError, PushError, Storage,
+    backoff::Spin,
+};
+
+/// Whether one or many handles operate on a side of the queue.
+pub trait Role: role::Sealed {}
+
+/// The only handle of its side. It is neither `Clone` nor `Sync`.
+///
+/// ```compile_fail
+/// use nblfq::HeapBackedQueue;
+/// use nblfq::split::Single;
+///
+/// let (tx, _rx) = HeapBackedQueue::<i32>::new(2).split::<Single, Single>();
+/// let _ = tx.clone();
+/// ```
+///
+/// ```compile_fail
+/// use nblfq::HeapBackedQueue;
+/// use nblfq::split::Single;
+///
+/// fn shared<T: Sync>(_: &T) {}
+///
+/// let (_tx, rx) = HeapBackedQueue::<i32>::new(2).split::<Single, Single>();
+/// shared(&rx);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Single;
+
+/// One of many handles of its side. It is `Clone` and `Sync`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Multi;
+
+impl Role for Single {}
+impl Role for Multi {}
+
+mod role {
+    use super::*;
+
+    pub trait Sealed {
+        /// The state a handle keeps between operations
+        type Cursor: Default;
+    }
+
+    impl Sealed for super::Single {
+        // the `Cell` makes the handle `!Sync`
+        type Cursor = Cell<Cursor>;
+    }
+
+    impl Sealed for super::Multi {
+        type Cursor = ();
+    }
+}
+
+/// The handles returned by `HeapBackedQueue::split`
+type Handles<T, P, C, A, L, B, S> = (Producer<T, P, A, L, B, S>, Consumer<T, C, A, L, B, S>);
+
+/// Takes the cursor out of its cell for the duration of `op`.
+fn with_cursor<R>(cursor: &Cell<Cursor>, op: impl FnOnce(&mut Cursor) -> R) -> R {
+    let mut current = cursor.get();
+    let res = op(&mut current);
+    cursor.set(current);
+    res
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> HeapBackedQueue<T, A, L, B, S> {
+    /// Splits the queue into a producer and a consumer handle, whose roles are `P` and `C`.
+    ///
+    /// Items pushed before the split remain in the queue.
+    /// `force_push` is not available on the handles, since it pops on the producer side.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::HeapBackedQueue;
+    /// use nblfq::split::Single;
+    ///
+    /// let q = HeapBackedQueue::new(2);
+    /// q.push(10).unwrap();
+    ///
+    /// let (tx, rx) = q.split::<Single, Single>();
+    /// tx.push(20).unwrap();
+    ///
+    /// assert_eq!(rx.pop(), Some(10));
+    /// assert_eq!(rx.pop(), Some(20));
+    /// assert_eq!(rx.pop(), None);
+    /// ```
+    pub fn split<P: Role, C: Role>(self) -> Handles<T, P, C, A, L, B, S> {
+        let queue = Arc::new(self);
+        (
+            Producer {
+                queue: queue.clone(),
+                cursor: Default::default(),
+            },
+            Consumer {
+                queue,
+                cursor: Default::default(),
+            },
+        )
+    }
+}
+
+/// The pushing half of a split `HeapBackedQueue`.
+pub struct Producer<
+    T,
+    R: Role = Multi,
+    A: Allocator = Global,
+    L: CellLayout = Dense,
+    B: Backoff = Spin,
+    S: Storage = DefaultStorage,
+> {
+    queue: Arc<HeapBackedQueue<T, A, L, B, S>>,
+    cursor: R::Cursor,
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Producer<T, Single, A, L, B, S> {
+    /// Attempts to push an item into the queue.
+    /// Returns the item as an error if the queue is full or closed.
+    pub fn push(&self, item: T) -> Result<(), PushError<T>> {
+        with_cursor(&self.cursor, |cursor| self.queue.push_single(cursor, item))
+    }
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Producer<T, Multi, A, L, B, S> {
+    /// Attempts to push an item into the queue.
+    /// Returns the item as an error if the queue is full or closed.
+    pub fn push(&self, item: T) -> Result<(), PushError<T>> {
+        self.queue.push(item)
+    }
+}
+
+impl<T, R: Role, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Producer<T, R, A, L, B, S> {
+    /// Closes the queue, see `HeapBackedQueue::close`.
+    pub fn close(&self) -> bool {
+        self.queue.close()
+    }
+
+    /// Indicates whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Returns the number of items in the queue.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Indicates whether the queue is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Indicates whether the queue is full.
+    /// The result may be stale.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Clone
+    for Producer<T, Multi, A, L, B, S>
+{
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+            cursor: (),
+        }
+    }
+}
+
+impl<T, R: Role, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Debug
+    for Producer<T, R, A, L, B, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Producer { ... }")
+    }
+}
+
+/// The popping half of a split `HeapBackedQueue`.
+pub struct Consumer<
+    T,
+    R: Role = Multi,
+    A: Allocator = Global,
+    L: CellLayout = Dense,
+    B: Backoff = Spin,
+    S: Storage = DefaultStorage,
+> {
+    queue: Arc<HeapBackedQueue<T, A, L, B, S>>,
+    cursor: R::Cursor,
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Consumer<T, Single, A, L, B, S> {
+    /// Attempts to pop an item from the queue.
+    /// Returns None if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        with_cursor(&self.cursor, |cursor| self.queue.pop_single(cursor))
+    }
+
+    /// Attempts to pop an item, see `HeapBackedQueue::try_pop`.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        with_cursor(&self.cursor, |cursor| self.queue.try_pop_single(cursor))
+    }
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Consumer<T, Multi, A, L, B, S> {
+    /// Attempts to pop an item from the queue.
+    /// Returns None if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    /// Attempts to pop an item, see `HeapBackedQueue::try_pop`.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.queue.try_pop()
+    }
+}
+
+impl<T, R: Role, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Consumer<T, R, A, L, B, S> {
+    /// Closes the queue, see `HeapBackedQueue::close`.
+    pub fn close(&self) -> bool {
+        self.queue.close()
+    }
+
+    /// Indicates whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    /// Returns the capacity of the queue.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Returns the number of items in the queue.
+    /// This value may be stale.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Indicates whether the queue is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Indicates whether the queue is full.
+    /// The result may be stale.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+}
+
+impl<T, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Clone
+    for Consumer<T, Multi, A, L, B, S>
+{
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+            cursor: (),
+        }
+    }
+}
+
+impl<T, R: Role, A: Allocator, L: CellLayout, B: Backoff, S: Storage> Debug
+    for Consumer<T, R, A, L, B, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Consumer { ... }")
+    }
+}
@@ -4,9 +4,12 @@
 //! https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-queue
 
 use core::sync::atomic::{AtomicUsize, Ordering};
-use std::{thread::scope, vec::Vec};
+use std::{alloc::Global, thread::scope, thread::yield_now, vec::Vec};
 
-use crate::HeapBackedQueue;
+use crate::{
+    DWord, Dense, HeapBackedQueue, Padded, PopError, PushError, Storage, backoff::Spin,
+    components::Item,
+};
 
 #[test]
 fn smoke() {
@@ -40,6 +43,33 @@ fn capacity() {
     }
 }
 
+#[test]
+fn pow2_capacity() {
+    let q = HeapBackedQueue::new_pow2(5);
+    assert_eq!(q.capacity(), 8);
+
+    // wrap around several times, with pushes and pops out of step
+    for round in 0..100 {
+        for i in 0..5 {
+            q.push(round * 5 + i).unwrap();
+        }
+        for i in 0..5 {
+            assert_eq!(q.pop(), Some(round * 5 + i));
+        }
+    }
+    for i in 0..8 {
+        q.push(i).unwrap();
+    }
+    assert!(q.is_full());
+    assert_eq!(q.push(8), Err(PushError::Full(8)));
+    for i in 0..8 {
+        assert_eq!(q.pop(), Some(i));
+    }
+    assert!(q.is_empty());
+
+    assert_eq!(HeapBackedQueue::<usize>::new_pow2(8).capacity(), 8);
+}
+
 #[test]
 fn len_empty_full() {
     let q = HeapBackedQueue::new(2);
@@ -267,7 +297,7 @@ fn mpmc_ring_buffer() {
         for _ in 0..THREADS {
             scope.spawn(|| {
                 for i in 0..COUNT {
-                    if let Some(n) = q.force_push(i) {
+                    if let Ok(Some(n)) = q.force_push(i) {
                         v[n].fetch_add(1, Ordering::SeqCst);
                     }
                 }
@@ -303,7 +333,7 @@ fn linearizable() {
 
             scope.spawn(|| {
                 for _ in 0..COUNT {
-                    if q.force_push(0).is_none() {
+                    if q.force_push(0).unwrap().is_none() {
                         q.pop().unwrap();
                     }
                 }
@@ -364,6 +394,306 @@ fn drops() {
     }
 }
 
+#[test]
+fn close() {
+    let q = HeapBackedQueue::new(2);
+    q.push(1).unwrap();
+    assert!(!q.is_closed());
+
+    assert!(q.close());
+    assert!(!q.close());
+    assert!(q.is_closed());
+    assert_eq!(q.push(2), Err(PushError::Closed(2)));
+    assert_eq!(q.force_push(3), Err(3));
+
+    // remaining items are still popped
+    assert_eq!(q.try_pop(), Ok(1));
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn close_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 4;
+
+    let q = HeapBackedQueue::new(3);
+    let pushed = AtomicUsize::new(0);
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                loop {
+                    match q.try_pop() {
+                        Ok(_) => {
+                            popped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(PopError::Empty) => yield_now(),
+                        Err(PopError::Closed) => break,
+                    }
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    loop {
+                        match q.push(i) {
+                            Ok(()) => {
+                                pushed.fetch_add(1, Ordering::SeqCst);
+                                break;
+                            }
+                            Err(PushError::Full(_)) => yield_now(),
+                            Err(PushError::Closed(_)) => return,
+                        }
+                    }
+                }
+            });
+        }
+        scope.spawn(|| {
+            while pushed.load(Ordering::SeqCst) < COUNT {
+                yield_now();
+            }
+            q.close();
+        });
+    });
+
+    // every accepted item has been popped before the queue reported Closed
+    assert_eq!(pushed.load(Ordering::SeqCst), popped.load(Ordering::SeqCst));
+    assert!(q.is_empty());
+}
+
+#[test]
+fn force_push_evicts_once() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 4;
+
+    let q: HeapBackedQueue<usize> = HeapBackedQueue::new(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for _ in 0..COUNT {
+                if let Some(n) = q.pop() {
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    if let Some(n) = q.force_push(i).unwrap() {
+                        v[n].fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+        }
+    });
+
+    // every item has been either popped, evicted or is still queued, exactly once
+    for n in q {
+        v[n].fetch_add(1, Ordering::SeqCst);
+    }
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn batch() {
+    let q = HeapBackedQueue::new(4);
+
+    // batches of 3 start at every offset of the buffer and wrap around
+    for round in 0..8 {
+        let mut items = 3 * round..3 * round + 3;
+        assert_eq!(q.push_iter(&mut items), (3, None));
+        assert_eq!(q.pop_n(2), [3 * round, 3 * round + 1]);
+        let mut out = Vec::new();
+        assert_eq!(q.pop_into(&mut out, 4), 1);
+        assert_eq!(out, [3 * round + 2]);
+    }
+
+    // the item, which did not fit, is handed back and the rest is left in the iterator
+    let mut items = 0..6;
+    assert_eq!(q.push_iter(&mut items), (4, Some(PushError::Full(4))));
+    assert_eq!(items.next(), Some(5));
+    assert_eq!(q.pop_n(10), [0, 1, 2, 3]);
+
+    assert_eq!(q.push_slice(&[1, 2, 3, 4, 5]), 4);
+    assert_eq!(q.pop_n(10), [1, 2, 3, 4]);
+    assert!(q.is_empty());
+}
+
+#[test]
+fn batch_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 64;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+    const BATCH: usize = 8;
+
+    let q: HeapBackedQueue<usize> = HeapBackedQueue::new(20);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut buf = Vec::with_capacity(BATCH);
+                while popped.load(Ordering::SeqCst) < COUNT * THREADS {
+                    buf.clear();
+                    let n = q.pop_into(&mut buf, BATCH);
+                    if n == 0 {
+                        yield_now();
+                    }
+                    for &i in &buf {
+                        v[i].fetch_add(1, Ordering::SeqCst);
+                    }
+                    popped.fetch_add(n, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut items = (0..COUNT).peekable();
+                while items.peek().is_some() {
+                    let (_, rejected) = q.push_iter(&mut items.by_ref().take(BATCH));
+                    if let Some(PushError::Full(i)) = rejected {
+                        while q.push(i).is_err() {
+                            yield_now();
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn padded_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+
+    let q: HeapBackedQueue<usize, Global, Padded> = HeapBackedQueue::with_layout(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while q.push(i).is_err() {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+/// Transfers `COUNT` items through a queue of capacity 3 encoded with `S`,
+/// so the round counters wrap around many times.
+fn storage_mpmc<S: Storage>() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 2;
+
+    let q: HeapBackedQueue<usize, Global, Dense, Spin, S> = HeapBackedQueue::with_layout(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while q.push(i).is_err() {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn tagged_storage() {
+    storage_mpmc::<crate::Tagged>();
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn align_tagged_storage() {
+    storage_mpmc::<crate::AlignTagged>();
+}
+
+#[test]
+fn dword_storage() {
+    storage_mpmc::<DWord>();
+}
+
+#[test]
+fn cell_layout() {
+    use crate::components::Cell;
+    use core::mem::{align_of, size_of};
+
+    assert_eq!(size_of::<Cell<usize, Dense>>(), size_of::<Item<usize>>());
+    assert!(align_of::<Cell<usize, Padded>>() >= 64);
+    assert_eq!(
+        size_of::<Cell<usize, Padded>>(),
+        align_of::<Cell<usize, Padded>>()
+    );
+}
+
 #[test]
 fn into_iter() {
     let q = HeapBackedQueue::new(100);
@@ -374,3 +704,320 @@ fn into_iter() {
         assert_eq!(i, j);
     }
 }
+
+#[test]
+fn allocator() {
+    use core::{alloc::Layout, ptr::NonNull};
+    use std::alloc::{AllocError, Allocator};
+
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a AtomicUsize);
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    let live = AtomicUsize::new(0);
+    let q = HeapBackedQueue::new_in(4, Counting(&live));
+    // buffer, pool ring and preallocated boxes
+    let allocated = live.load(Ordering::SeqCst);
+    assert_eq!(allocated, 2 + 4);
+
+    for i in 0..100 {
+        q.push(i).unwrap();
+        assert_eq!(q.pop(), Some(i));
+    }
+    assert_eq!(live.load(Ordering::SeqCst), allocated);
+
+    for i in 0..4 {
+        q.push(i).unwrap();
+    }
+    drop(q);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn peek() {
+    let q = HeapBackedQueue::new(2);
+    assert_eq!(q.peek_with(|item| *item), None);
+
+    // wrap around, so the oldest item is not in the first cell
+    for i in 0..3 {
+        q.push(i).unwrap();
+        q.pop().unwrap();
+    }
+    q.push(3).unwrap();
+    q.push(4).unwrap();
+
+    // nested reads of the same item
+    assert_eq!(
+        q.peek_with(|first| q.peek_with(|second| (*first, *second))),
+        Some(Some((3, 3)))
+    );
+    assert_eq!(q.pop(), Some(3));
+    assert_eq!(q.peek_with(|item| *item), Some(4));
+    assert_eq!(q.len(), 1);
+}
+
+#[test]
+fn peek_does_not_block_other_pops() {
+    let q = HeapBackedQueue::new(3);
+    for i in 0..3 {
+        q.push(i).unwrap();
+    }
+
+    // only a pop of the item being read waits for the read
+    q.peek_with(|item| {
+        assert_eq!(*item, 0);
+        assert_eq!(q.pop_if(|item| *item != 0), None);
+        assert_eq!(q.snapshot(), [0, 1, 2]);
+    });
+    assert_eq!(q.pop(), Some(0));
+
+    scope(|scope| {
+        let pop = q
+            .peek_with(|item| {
+                assert_eq!(*item, 1);
+                // popped by another thread, which waits until this read is done
+                let pop = scope.spawn(|| q.pop());
+                while q.peek_with(|item| *item) == Some(1) {
+                    yield_now();
+                }
+                assert_eq!(*item, 1);
+                pop
+            })
+            .unwrap();
+        assert_eq!(pop.join().unwrap(), Some(1));
+    });
+    assert_eq!(q.pop(), Some(2));
+}
+
+#[test]
+fn pop_if() {
+    let q = HeapBackedQueue::new(3);
+    assert_eq!(q.pop_if(|_| true), None);
+
+    for i in 0..3 {
+        q.push(i).unwrap();
+    }
+    assert_eq!(q.pop_if(|item| *item > 0), None);
+    assert_eq!(q.pop_if(|item| *item == 0), Some(0));
+    assert_eq!(q.pop_if(|item| *item == 1), Some(1));
+    assert_eq!(q.len(), 1);
+    assert_eq!(q.pop(), Some(2));
+}
+
+#[test]
+fn peek_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 5_000;
+    const THREADS: usize = 2;
+
+    let q = HeapBackedQueue::new(3);
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    let mut next = std::format!("{i}");
+                    while let Err(err) = q.push(next) {
+                        next = err.into_inner();
+                        yield_now();
+                    }
+                }
+            });
+            scope.spawn(|| {
+                while popped.load(Ordering::Relaxed) < COUNT * THREADS {
+                    // peeked items are never moved out during the read
+                    q.peek_with(|item| assert!(item.parse::<usize>().unwrap() < COUNT));
+                    let item = if popped.load(Ordering::Relaxed).is_multiple_of(2) {
+                        q.pop_if(|item| item.parse::<usize>().unwrap() < COUNT)
+                    } else {
+                        q.pop()
+                    };
+                    match item {
+                        Some(item) => {
+                            assert!(item.parse::<usize>().unwrap() < COUNT);
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => yield_now(),
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(popped.load(Ordering::Relaxed), COUNT * THREADS);
+    assert!(q.is_empty());
+}
+
+#[test]
+fn snapshot() {
+    let q = HeapBackedQueue::new(3);
+    assert!(q.snapshot().is_empty());
+
+    // wrap around, so the items span the end of the buffer
+    for i in 0..5 {
+        q.push(i).unwrap();
+        if i < 3 {
+            q.pop().unwrap();
+        }
+    }
+    q.push(5).unwrap();
+    assert_eq!(q.snapshot(), [3, 4, 5]);
+    assert_eq!(
+        std::format!("{:?}", q.debug_items()),
+        "HeapBackedQueue { items: [3, 4, 5], capacity: 3, head: 0, tail: 0, counts: [1, 1, 1] }"
+    );
+    assert_eq!(
+        std::format!("{q:?}"),
+        "HeapBackedQueue { capacity: 3, head: 0, tail: 0, counts: [1, 1, 1] }"
+    );
+
+    assert_eq!(q.pop(), Some(3));
+    assert_eq!(q.snapshot(), [4, 5]);
+    assert_eq!(q.len(), 2);
+}
+
+#[test]
+fn snapshot_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 2_000;
+
+    let q = HeapBackedQueue::new(4);
+    let done = AtomicUsize::new(0);
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                let mut next = std::format!("{i}");
+                while let Err(err) = q.push(next) {
+                    next = err.into_inner();
+                    yield_now();
+                }
+            }
+        });
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                loop {
+                    if let Some(item) = q.pop() {
+                        assert_eq!(item, std::format!("{i}"));
+                        break;
+                    }
+                    yield_now();
+                }
+            }
+            done.store(1, Ordering::Relaxed);
+        });
+        scope.spawn(|| {
+            while done.load(Ordering::Relaxed) == 0 {
+                // a snapshot of a single producer's items is in order
+                let items = q
+                    .snapshot()
+                    .iter()
+                    .map(|item| item.parse::<usize>().unwrap())
+                    .collect::<Vec<_>>();
+                assert!(items.len() <= 4);
+                assert!(items.windows(2).all(|w| w[0] < w[1]));
+                yield_now();
+            }
+        });
+    });
+}
+
+#[test]
+fn clear_and_drain() {
+    let q = HeapBackedQueue::new(3);
+    q.clear();
+
+    // a full queue, whose items span the end of the buffer
+    for i in 0..5 {
+        q.push(i).unwrap();
+        if i < 2 {
+            q.pop().unwrap();
+        }
+    }
+    assert!(q.is_full());
+    let mut drain = q.drain();
+    assert_eq!(drain.next(), Some(2));
+    q.push(5).unwrap();
+    assert_eq!(drain.collect::<Vec<_>>(), [3, 4]);
+    assert_eq!(q.pop(), Some(5));
+
+    q.push(6).unwrap();
+    q.push(7).unwrap();
+    q.clear();
+    assert!(q.is_empty());
+    q.push(8).unwrap();
+    assert_eq!(q.pop(), Some(8));
+}
+
+#[test]
+fn retain() {
+    let q = HeapBackedQueue::new(4);
+    for i in 0..4 {
+        q.push(i).unwrap();
+    }
+    assert_eq!(q.retain(|item| item % 2 == 1), Ok(()));
+    assert_eq!(q.snapshot(), [1, 3]);
+
+    // a push into the cell freed by retain leaves no room for the kept item
+    q.push(5).unwrap();
+    q.push(7).unwrap();
+    let mut pushed = false;
+    let res = q.retain(|_| {
+        if !pushed {
+            pushed = true;
+            q.push(9).unwrap();
+        }
+        true
+    });
+    assert_eq!(res, Err(PushError::Full(1)));
+    assert_eq!(q.pop_n(4), [3, 5, 7, 9]);
+
+    q.push(1).unwrap();
+    q.close();
+    assert_eq!(q.retain(|_| true), Err(PushError::Closed(1)));
+    assert_eq!(q.retain(|_| true), Ok(()));
+}
+
+#[test]
+fn clear_under_producers() {
+    const THREADS: usize = 2;
+
+    let q = HeapBackedQueue::new(8);
+    let done = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                while done.load(Ordering::Relaxed) == 0 {
+                    let _ = q.push(1);
+                    yield_now();
+                }
+            });
+        }
+        // each call pops at most the capacity, however fast the producers refill the queue
+        for _ in 0..100 {
+            // fill the queue, nothing else pops from it
+            while q.push(0).is_ok() {}
+            q.clear();
+            assert!(q.drain().count() <= 8);
+            assert_eq!(q.retain(|_| false), Ok(()));
+        }
+        done.store(1, Ordering::Relaxed);
+    });
+}
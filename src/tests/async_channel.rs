@@ -0,0 +1,334 @@
+//! Testing for the async channel
+
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::{Pin, pin},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    task::Wake,
+    thread::{self, scope},
+    vec::Vec,
+};
+
+use crate::async_channel::{self, RecvError, SendError, TryRecvError, TrySendError};
+
+struct Unpark(thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+            return res;
+        }
+        thread::park();
+    }
+}
+
+/// Counts its wakeups.
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Counter {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn smoke() {
+    let (tx, rx) = async_channel::bounded(1);
+    block_on(async {
+        tx.send(7).await.unwrap();
+        assert_eq!(rx.recv().await, Ok(7));
+
+        tx.send(8).await.unwrap();
+        assert_eq!(rx.recv().await, Ok(8));
+    });
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+}
+
+#[test]
+fn try_send_full() {
+    let (tx, rx) = async_channel::bounded(1);
+    assert_eq!(tx.try_send(1), Ok(()));
+    assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(tx.try_send(2), Ok(()));
+}
+
+#[test]
+fn disconnect_senders() {
+    let (tx, rx) = async_channel::bounded(2);
+    let tx2 = tx.clone();
+    tx.try_send(1).unwrap();
+    drop(tx);
+    tx2.try_send(2).unwrap();
+    drop(tx2);
+
+    // queued items are still received
+    block_on(async {
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Ok(2));
+        assert_eq!(rx.recv().await, Err(RecvError));
+    });
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn disconnect_receivers() {
+    let (tx, rx) = async_channel::bounded(2);
+    drop(rx.clone());
+    tx.try_send(1).unwrap();
+    drop(rx);
+
+    assert_eq!(block_on(tx.send(2)), Err(SendError(2)));
+    assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+}
+
+#[test]
+fn wake_on_send() {
+    let (tx, rx) = async_channel::bounded(1);
+    let counter = Arc::new(Counter::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let mut recv = pin!(rx.recv());
+    assert!(recv.as_mut().poll(&mut cx).is_pending());
+    // polling again with the same waker does not register twice
+    assert!(recv.as_mut().poll(&mut cx).is_pending());
+    assert_eq!(counter.count(), 0);
+
+    tx.try_send(1).unwrap();
+    assert_eq!(counter.count(), 1);
+    assert_eq!(recv.poll(&mut cx), Poll::Ready(Ok(1)));
+}
+
+#[test]
+fn wake_on_recv() {
+    let (tx, rx) = async_channel::bounded(1);
+    let counter = Arc::new(Counter::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    tx.try_send(1).unwrap();
+    let mut send = pin!(tx.send(2));
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(counter.count(), 1);
+    assert_eq!(send.poll(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(rx.try_recv(), Ok(2));
+}
+
+#[test]
+fn dropped_future_passes_notification_on() {
+    let (tx, rx) = async_channel::bounded(1);
+    let first = Arc::new(Counter::default());
+    let second = Arc::new(Counter::default());
+    let first_waker = Waker::from(first.clone());
+    let second_waker = Waker::from(second.clone());
+
+    let mut a = rx.recv();
+    let mut b = pin!(rx.recv());
+    assert!(
+        Pin::new(&mut a)
+            .poll(&mut Context::from_waker(&first_waker))
+            .is_pending()
+    );
+    assert!(
+        b.as_mut()
+            .poll(&mut Context::from_waker(&second_waker))
+            .is_pending()
+    );
+
+    tx.try_send(1).unwrap();
+    assert_eq!((first.count(), second.count()), (1, 0));
+
+    // the first receiver gives up, so the second one has to take the item
+    drop(a);
+    assert_eq!(second.count(), 1);
+    assert_eq!(
+        b.poll(&mut Context::from_waker(&second_waker)),
+        Poll::Ready(Ok(1))
+    );
+}
+
+#[test]
+fn wake_on_disconnect() {
+    let (tx, rx) = async_channel::bounded::<i32>(1);
+
+    scope(|scope| {
+        scope.spawn(|| assert_eq!(block_on(rx.recv()), Err(RecvError)));
+        scope.spawn(move || drop(tx));
+    });
+}
+
+#[test]
+fn spsc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 100_000;
+
+    let (tx, rx) = async_channel::bounded(3);
+
+    scope(|scope| {
+        scope.spawn(move || {
+            block_on(async {
+                for i in 0..COUNT {
+                    assert_eq!(rx.recv().await, Ok(i));
+                }
+                assert_eq!(rx.recv().await, Err(RecvError));
+            })
+        });
+
+        scope.spawn(move || {
+            block_on(async {
+                for i in 0..COUNT {
+                    tx.send(i).await.unwrap();
+                }
+            })
+        });
+    })
+}
+
+#[test]
+fn mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+
+    let (tx, rx) = async_channel::bounded::<usize>(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            let rx = rx.clone();
+            let v = &v;
+            scope.spawn(move || {
+                block_on(async {
+                    while let Ok(n) = rx.recv().await {
+                        v[n].fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            });
+        }
+        for _ in 0..THREADS {
+            let tx = tx.clone();
+            scope.spawn(move || {
+                block_on(async {
+                    for i in 0..COUNT {
+                        tx.send(i).await.unwrap();
+                    }
+                })
+            });
+        }
+        drop(tx);
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn stream_and_sink() {
+    use core::future::poll_fn;
+    use futures_core::Stream;
+    use futures_sink::Sink;
+
+    let (mut tx, mut rx) = async_channel::bounded(2);
+
+    scope(|scope| {
+        scope.spawn(move || {
+            block_on(async {
+                for i in 0..100 {
+                    poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx))
+                        .await
+                        .unwrap();
+                    Pin::new(&mut tx).start_send(i).unwrap();
+                }
+                poll_fn(|cx| Pin::new(&mut tx).poll_close(cx))
+                    .await
+                    .unwrap();
+            })
+        });
+
+        block_on(async {
+            for i in 0..100 {
+                let next = poll_fn(|cx| Pin::new(&mut rx).poll_next(cx)).await;
+                assert_eq!(next, Some(i));
+            }
+            // the stream ends once all senders have been closed
+            assert_eq!(poll_fn(|cx| Pin::new(&mut rx).poll_next(cx)).await, None);
+        });
+    });
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn close_disconnects_sender() {
+    use core::future::poll_fn;
+    use futures_core::Stream;
+    use futures_sink::Sink;
+
+    let (mut tx, mut rx) = async_channel::bounded(1);
+    let mut other = tx.clone();
+
+    block_on(async {
+        // the buffered item is sent before the sender is closed
+        Pin::new(&mut tx).start_send(1).unwrap();
+        Pin::new(&mut tx).start_send(2).unwrap();
+        let mut close = pin!(poll_fn(|cx| Pin::new(&mut tx).poll_close(cx)));
+        assert!(
+            close
+                .as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+                .is_pending()
+        );
+        assert_eq!(rx.recv().await, Ok(1));
+        close.await.unwrap();
+        assert_eq!(rx.recv().await, Ok(2));
+
+        // a closed sender, and its clones, cannot send anymore
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+        assert_eq!(tx.send(3).await, Err(SendError(3)));
+        assert_eq!(Pin::new(&mut tx).start_send(3), Err(SendError(3)));
+        assert_eq!(tx.clone().try_send(3), Err(TrySendError::Disconnected(3)));
+        poll_fn(|cx| Pin::new(&mut tx).poll_close(cx))
+            .await
+            .unwrap();
+
+        // the other sender is still connected
+        other.send(4).await.unwrap();
+        assert_eq!(rx.recv().await, Ok(4));
+
+        // the stream ends once the last sender is closed, while it is still alive
+        poll_fn(|cx| Pin::new(&mut other).poll_close(cx))
+            .await
+            .unwrap();
+        assert_eq!(poll_fn(|cx| Pin::new(&mut rx).poll_next(cx)).await, None);
+        assert_eq!(rx.recv().await, Err(RecvError));
+    });
+    drop((tx, other));
+}
@@ -0,0 +1,87 @@
+//! Testing the backoff strategies under contention
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{
+    Backoff, Dense, HeapBackedQueue, InlineQueue,
+    backoff::{ExponentialSpin, NoBackoff, Spin, SpinThenSleep, SpinThenYield},
+};
+
+#[cfg(miri)]
+const COUNT: usize = 50;
+#[cfg(not(miri))]
+const COUNT: usize = 10_000;
+const THREADS: usize = 4;
+
+/// Transfers `COUNT` items from each of `THREADS` producers to `THREADS` consumers.
+fn mpmc(push: impl Fn(usize) -> bool + Sync, pop: impl Fn() -> Option<usize> + Sync) {
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(n) = pop() {
+                            break n;
+                        }
+                        yield_now();
+                    };
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while !push(i) {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+fn heap_backed<B: Backoff>() {
+    let q = HeapBackedQueue::<usize, _, Dense, B>::with_layout(3);
+    mpmc(|i| q.push(i).is_ok(), || q.pop());
+}
+
+fn inline<B: Backoff>() {
+    let q = InlineQueue::<usize, Dense, B>::with_layout(3);
+    mpmc(|i| q.push(i).is_ok(), || q.pop());
+}
+
+#[test]
+fn no_backoff() {
+    heap_backed::<NoBackoff>();
+    inline::<NoBackoff>();
+}
+
+#[test]
+fn spin() {
+    heap_backed::<Spin>();
+    inline::<Spin>();
+}
+
+#[test]
+fn exponential_spin() {
+    heap_backed::<ExponentialSpin>();
+    inline::<ExponentialSpin>();
+}
+
+#[test]
+fn spin_then_yield() {
+    heap_backed::<SpinThenYield>();
+    inline::<SpinThenYield>();
+}
+
+#[test]
+fn spin_then_sleep() {
+    heap_backed::<SpinThenSleep>();
+    inline::<SpinThenSleep>();
+}
@@ -0,0 +1,117 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{BorrowQueue, PopError, PushError};
+
+#[test]
+fn smoke() {
+    let items = [7, 8];
+    let q: BorrowQueue<1, i32> = BorrowQueue::new();
+
+    q.push(&items[0]).unwrap();
+    assert_eq!(q.push(&items[1]), Err(PushError::Full(&items[1])));
+    assert_eq!(q.pop(), Some(&7));
+    assert_eq!(q.force_push(&items[1]), Ok(None));
+    assert_eq!(q.force_push(&items[0]), Ok(Some(&8)));
+    assert_eq!(q.pop(), Some(&7));
+    assert!(q.pop().is_none());
+
+    q.close();
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+}
+
+#[test]
+fn batch() {
+    let items = (0..10).collect::<Vec<_>>();
+    let q: BorrowQueue<4, i32> = BorrowQueue::new();
+
+    assert_eq!(q.push_slice(&items), 4);
+    let mut out: Vec<&i32> = Vec::new();
+    assert_eq!(q.pop_into(&mut out, 2), 2);
+    assert_eq!(out, [&0, &1]);
+    assert_eq!(q.push_slice(&items[4..]), 2);
+    assert_eq!(q.into_iter().copied().collect::<Vec<_>>(), [2, 3, 4, 5]);
+}
+
+#[test]
+fn scoped_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 2;
+
+    // owned by this stack frame, not leaked
+    let items = (0..COUNT).collect::<Vec<_>>();
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+    let q: BorrowQueue<3, usize> = BorrowQueue::new();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[*n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            scope.spawn(|| {
+                for item in &items {
+                    while q.push(item).is_err() {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn peek_and_pop_if() {
+    let items = [1, 2];
+    let q: BorrowQueue<2, i32> = BorrowQueue::new();
+    assert_eq!(q.peek(), None);
+
+    q.push(&items[0]).unwrap();
+    q.push(&items[1]).unwrap();
+    assert_eq!(q.peek(), Some(&1));
+    assert_eq!(q.pop_if(|item| *item == 2), None);
+    assert_eq!(q.pop_if(|item| *item == 1), Some(&1));
+    assert_eq!(q.pop(), Some(&2));
+}
+
+#[test]
+fn iter() {
+    let items = [1, 2];
+    let q: BorrowQueue<2, i32> = BorrowQueue::new();
+    q.push(&items[0]).unwrap();
+    q.push(&items[1]).unwrap();
+
+    assert!(q.iter().eq(&items));
+    assert_eq!(
+        std::format!("{:?}", q.debug_items()),
+        "BorrowQueue { items: [1, 2], capacity: 2, head: 0, tail: 0, counts: [0, 0] }"
+    );
+}
+
+#[test]
+fn clear_drain_and_retain() {
+    let items = [1, 2, 3];
+    let q: BorrowQueue<3, i32> = BorrowQueue::new();
+
+    q.push_slice(&items);
+    assert_eq!(q.retain(|item| *item != 2), Ok(()));
+    assert_eq!(q.drain().collect::<Vec<_>>(), [&1, &3]);
+
+    q.push(&items[0]).unwrap();
+    q.clear();
+    assert!(q.is_empty());
+}
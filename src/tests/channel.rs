@@ -0,0 +1,125 @@
+//! Testing for the blocking channel
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, vec::Vec};
+
+use crate::channel::{self, RecvError, SendError, TryRecvError, TrySendError};
+
+#[test]
+fn smoke() {
+    let (tx, rx) = channel::bounded(1);
+    tx.send(7).unwrap();
+    assert_eq!(rx.recv(), Ok(7));
+
+    tx.send(8).unwrap();
+    assert_eq!(rx.recv(), Ok(8));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+}
+
+#[test]
+fn try_send_full() {
+    let (tx, rx) = channel::bounded(1);
+    assert_eq!(tx.try_send(1), Ok(()));
+    assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(tx.try_send(2), Ok(()));
+}
+
+#[test]
+fn disconnect_senders() {
+    let (tx, rx) = channel::bounded(2);
+    let tx2 = tx.clone();
+    tx.send(1).unwrap();
+    drop(tx);
+    tx2.send(2).unwrap();
+    drop(tx2);
+
+    // queued items are still received
+    assert_eq!(rx.recv(), Ok(1));
+    assert_eq!(rx.recv(), Ok(2));
+    assert_eq!(rx.recv(), Err(RecvError));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn disconnect_receivers() {
+    let (tx, rx) = channel::bounded(2);
+    drop(rx.clone());
+    tx.send(1).unwrap();
+    drop(rx);
+
+    assert_eq!(tx.send(2), Err(SendError(2)));
+    assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+}
+
+#[test]
+fn wake_on_disconnect() {
+    let (tx, rx) = channel::bounded::<i32>(1);
+
+    scope(|scope| {
+        scope.spawn(|| assert_eq!(rx.recv(), Err(RecvError)));
+        scope.spawn(move || drop(tx));
+    });
+}
+
+#[test]
+fn spsc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 100_000;
+
+    let (tx, rx) = channel::bounded(3);
+
+    scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..COUNT {
+                assert_eq!(rx.recv(), Ok(i));
+            }
+            assert_eq!(rx.recv(), Err(RecvError));
+        });
+
+        scope.spawn(move || {
+            for i in 0..COUNT {
+                tx.send(i).unwrap();
+            }
+        });
+    })
+}
+
+#[test]
+fn mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+
+    let (tx, rx) = channel::bounded::<usize>(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            let rx = rx.clone();
+            let v = &v;
+            scope.spawn(move || {
+                for n in rx {
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            let tx = tx.clone();
+            scope.spawn(move || {
+                for i in 0..COUNT {
+                    tx.send(i).unwrap();
+                }
+            });
+        }
+        drop(tx);
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
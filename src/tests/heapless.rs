@@ -6,7 +6,7 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
 use std::{boxed::Box, thread::scope, vec::Vec};
 
-use crate::HeaplessQueue;
+use crate::{DWord, Dense, HeaplessQueue, Padded, PopError, PushError, backoff::Spin};
 
 #[test]
 fn smoke() {
@@ -264,7 +264,7 @@ fn mpmc_ring_buffer() {
             scope.spawn(|| {
                 for i in 0..COUNT {
                     let i: &'static usize = Box::leak(Box::new(i));
-                    if let Some(n) = q.force_push(i) {
+                    if let Ok(Some(n)) = q.force_push(i) {
                         v[*n].fetch_add(1, Ordering::SeqCst);
                     }
                 }
@@ -300,7 +300,7 @@ fn linearizable() {
 
             scope.spawn(|| {
                 for _ in 0..COUNT {
-                    if q.force_push(&0).is_none() {
+                    if q.force_push(&0).unwrap().is_none() {
                         q.pop().unwrap();
                     }
                 }
@@ -309,6 +309,43 @@ fn linearizable() {
     })
 }
 
+#[test]
+fn close() {
+    let q: HeaplessQueue<2, _> = HeaplessQueue::new();
+    q.push(&1).unwrap();
+    assert!(!q.is_closed());
+
+    assert!(q.close());
+    assert!(!q.close());
+    assert!(q.is_closed());
+    assert_eq!(q.push(&2), Err(PushError::Closed(&2)));
+    assert_eq!(q.force_push(&3), Err(&3));
+
+    // remaining items are still popped
+    assert_eq!(q.try_pop(), Ok(&1));
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn batch() {
+    static ITEMS: [usize; 5] = [0, 1, 2, 3, 4];
+    let q: HeaplessQueue<4, _> = HeaplessQueue::new();
+
+    for _ in 0..10 {
+        assert_eq!(q.push_slice(&ITEMS[..3]), 3);
+        let mut items = ITEMS.iter();
+        assert_eq!(q.push_iter(&mut items), (1, Some(PushError::Full(&1))));
+        assert_eq!(items.next(), Some(&2));
+
+        let mut out: Vec<&usize> = Vec::new();
+        assert_eq!(q.pop_into(&mut out, 3), 3);
+        assert_eq!(out, [&0, &1, &2]);
+        assert_eq!(q.pop_into(&mut out, 2), 1);
+        assert_eq!(out, [&0, &1, &2, &0]);
+    }
+}
+
 #[test]
 fn into_iter() {
     let q: HeaplessQueue<100, _> = HeaplessQueue::new();
@@ -321,3 +358,118 @@ fn into_iter() {
         assert_eq!(i, *j);
     }
 }
+
+#[test]
+fn static_queue() {
+    static Q: HeaplessQueue<4, usize> = HeaplessQueue::new();
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..4 {
+                Q.push(Box::leak(Box::new(i))).unwrap();
+            }
+        });
+    });
+
+    for i in 0..4 {
+        assert_eq!(Q.pop(), Some(&i));
+    }
+    assert!(Q.pop().is_none());
+}
+
+#[test]
+fn static_padded_queue() {
+    static Q: HeaplessQueue<4, usize, Padded> = HeaplessQueue::with_layout();
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..4 {
+                Q.push(Box::leak(Box::new(i))).unwrap();
+            }
+        });
+    });
+
+    for i in 0..4 {
+        assert_eq!(Q.pop(), Some(&i));
+    }
+    assert!(Q.pop().is_none());
+}
+
+#[test]
+fn static_dword_queue() {
+    static Q: HeaplessQueue<4, usize, Dense, Spin, DWord> = HeaplessQueue::with_layout();
+
+    // wrap around several times
+    for round in 0..10 {
+        for i in 0..3 {
+            Q.push(Box::leak(Box::new(round * 3 + i))).unwrap();
+        }
+        for i in 0..3 {
+            assert_eq!(Q.pop(), Some(&(round * 3 + i)));
+        }
+    }
+    assert!(Q.pop().is_none());
+}
+
+#[test]
+fn peek_and_pop_if() {
+    static ITEMS: [i32; 3] = [1, 2, 3];
+    let q: HeaplessQueue<2, i32> = HeaplessQueue::new();
+    assert_eq!(q.peek(), None);
+    assert_eq!(q.pop_if(|_| true), None);
+
+    q.push(&ITEMS[0]).unwrap();
+    q.push(&ITEMS[1]).unwrap();
+    assert_eq!(q.peek(), Some(&1));
+    assert_eq!(q.pop_if(|item| *item == 2), None);
+    assert_eq!(q.pop_if(|item| *item == 1), Some(&1));
+
+    // the peeked reference outlives the pop
+    let peeked = q.peek().unwrap();
+    assert_eq!(q.pop(), Some(&2));
+    assert_eq!(peeked, &2);
+}
+
+#[test]
+fn iter() {
+    static ITEMS: [i32; 4] = [1, 2, 3, 4];
+    let q: HeaplessQueue<3, i32> = HeaplessQueue::new();
+    assert_eq!(q.iter().count(), 0);
+
+    q.push(&ITEMS[0]).unwrap();
+    q.pop().unwrap();
+    q.push_slice(&ITEMS[1..]);
+    assert!(q.iter().eq(&ITEMS[1..]));
+    assert_eq!(
+        std::format!("{:?}", q.debug_items()),
+        "HeaplessQueue { items: [2, 3, 4], capacity: 3, head: 1, tail: 1, counts: [1, 0, 0] }"
+    );
+    assert_eq!(
+        std::format!("{q:?}"),
+        "HeaplessQueue { capacity: 3, head: 1, tail: 1, counts: [1, 0, 0] }"
+    );
+
+    assert_eq!(q.pop(), Some(&2));
+    assert!(q.iter().eq(&ITEMS[2..]));
+    assert_eq!(q.len(), 2);
+}
+
+#[test]
+fn clear_drain_and_retain() {
+    static ITEMS: [i32; 4] = [1, 2, 3, 4];
+    let q: HeaplessQueue<4, i32> = HeaplessQueue::new();
+
+    q.push_slice(&ITEMS);
+    assert_eq!(q.retain(|item| item % 2 == 0), Ok(()));
+    assert!(q.iter().eq([&2, &4]));
+
+    let mut drain = q.drain();
+    q.push(&ITEMS[0]).unwrap();
+    assert_eq!(drain.next(), Some(&2));
+    assert_eq!(drain.next(), Some(&4));
+    assert_eq!(drain.next(), None);
+
+    q.push(&ITEMS[1]).unwrap();
+    q.clear();
+    assert!(q.is_empty());
+}
@@ -0,0 +1,114 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{IndexQueue, Padded, PopError, PushError};
+
+#[test]
+fn smoke() {
+    let q = IndexQueue::new(1);
+    q.push(7).unwrap();
+    assert_eq!(q.pop(), Some(7));
+
+    q.push(8).unwrap();
+    assert_eq!(q.pop(), Some(8));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn boundary_values() {
+    let q = IndexQueue::new(2);
+
+    // 0 must not be mistaken for an empty cell
+    q.push(0).unwrap();
+    q.push(u32::MAX - 1).unwrap();
+    assert_eq!(q.push(1), Err(PushError::Full(1)));
+    assert_eq!(q.pop(), Some(0));
+    assert_eq!(q.pop(), Some(u32::MAX - 1));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+#[should_panic]
+fn reserved_value() {
+    let q = IndexQueue::new(2);
+    let _ = q.push(u32::MAX);
+}
+
+#[test]
+fn wrap_around() {
+    let q = IndexQueue::new_pow2(3);
+    assert_eq!(q.capacity(), 4);
+
+    for round in 0..100 {
+        for i in 0..3 {
+            q.push(round * 3 + i).unwrap();
+        }
+        for i in 0..3 {
+            assert_eq!(q.pop(), Some(round * 3 + i));
+        }
+    }
+    assert!(q.is_empty());
+}
+
+#[test]
+fn batch_and_force_push() {
+    let q = IndexQueue::new(3);
+
+    assert_eq!(q.push_slice(&[1, 2, 3, 4]), 3);
+    assert!(q.is_full());
+    assert_eq!(q.force_push(4), Ok(Some(1)));
+    assert_eq!(q.pop_n(5), [2, 3, 4]);
+}
+
+#[test]
+fn close() {
+    let q = IndexQueue::new(2);
+    q.push(1).unwrap();
+    assert!(q.close());
+
+    assert_eq!(q.push(2), Err(PushError::Closed(2)));
+    assert_eq!(q.force_push(2), Err(2));
+    assert_eq!(q.try_pop(), Ok(1));
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+}
+
+#[test]
+fn mpmc() {
+    #[cfg(miri)]
+    const COUNT: u32 = 50;
+    #[cfg(not(miri))]
+    const COUNT: u32 = 10_000;
+    const THREADS: usize = 2;
+
+    let q: IndexQueue<Padded> = IndexQueue::with_layout(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[n as usize].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while q.push(i).is_err() {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
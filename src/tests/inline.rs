@@ -0,0 +1,386 @@
+//! Testing for nblfq queue
+//!
+//! Tests adapted from crossbeam-queue's test suite.
+//! https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-queue
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{InlineQueue, Padded, PopError, PushError};
+
+#[test]
+fn smoke() {
+    let q = InlineQueue::new(1);
+    q.push(7).unwrap();
+    assert_eq!(q.pop(), Some(7));
+
+    q.push(8).unwrap();
+    assert_eq!(q.pop(), Some(8));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn smoke_long() {
+    let q = InlineQueue::new(10);
+    q.push(7).unwrap();
+    assert_eq!(q.pop(), Some(7));
+
+    q.push(8).unwrap();
+    q.push(9).unwrap();
+    assert_eq!(q.pop(), Some(8));
+    assert_eq!(q.pop(), Some(9));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn len_empty_full() {
+    let q = InlineQueue::new(2);
+
+    assert_eq!(q.len(), 0);
+    assert!(q.is_empty());
+    assert!(!q.is_full());
+
+    q.push(()).unwrap();
+
+    assert_eq!(q.len(), 1);
+    assert!(!q.is_empty());
+    assert!(!q.is_full());
+
+    q.push(()).unwrap();
+
+    assert_eq!(q.len(), 2);
+    assert!(!q.is_empty());
+    assert!(q.is_full());
+
+    q.pop().unwrap();
+
+    assert_eq!(q.len(), 1);
+    assert!(!q.is_empty());
+    assert!(!q.is_full());
+}
+
+#[test]
+fn wrap_around() {
+    let q = InlineQueue::new(3);
+
+    for i in 0..100 {
+        q.push(i).unwrap();
+        q.push(i + 1).unwrap();
+        assert_eq!(q.pop(), Some(i));
+        assert_eq!(q.pop(), Some(i + 1));
+    }
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn spsc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 100_000;
+
+    let q = InlineQueue::new(3);
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                loop {
+                    if let Some(x) = q.pop() {
+                        assert_eq!(x, i);
+                        break;
+                    }
+                    yield_now();
+                }
+            }
+            assert!(q.pop().is_none());
+        });
+
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                while q.push(i).is_err() {
+                    yield_now();
+                }
+            }
+        });
+    })
+}
+
+#[test]
+fn mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+
+    let q: InlineQueue<usize> = InlineQueue::new(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while q.push(i).is_err() {
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn drops() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq)]
+    struct DropCounter;
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let q = InlineQueue::new(50);
+    for _ in 0..30 {
+        q.push(DropCounter).unwrap();
+    }
+    for _ in 0..10 {
+        drop(q.pop().unwrap());
+    }
+    assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+
+    drop(q);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 30);
+}
+
+#[test]
+fn close() {
+    let q = InlineQueue::new(2);
+    q.push(1).unwrap();
+    assert!(!q.is_closed());
+
+    assert!(q.close());
+    assert!(!q.close());
+    assert!(q.is_closed());
+    assert_eq!(q.push(2), Err(PushError::Closed(2)));
+    assert_eq!(q.force_push(3), Err(3));
+
+    // remaining items are still popped
+    assert_eq!(q.try_pop(), Ok(1));
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn close_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 4;
+
+    let q = InlineQueue::new(3);
+    let pushed = AtomicUsize::new(0);
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                loop {
+                    match q.try_pop() {
+                        Ok(_) => {
+                            popped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(PopError::Empty) => yield_now(),
+                        Err(PopError::Closed) => break,
+                    }
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    loop {
+                        match q.push(i) {
+                            Ok(()) => {
+                                pushed.fetch_add(1, Ordering::SeqCst);
+                                break;
+                            }
+                            Err(PushError::Full(_)) => yield_now(),
+                            Err(PushError::Closed(_)) => return,
+                        }
+                    }
+                }
+            });
+        }
+        scope.spawn(|| {
+            while pushed.load(Ordering::SeqCst) < COUNT {
+                yield_now();
+            }
+            q.close();
+        });
+    });
+
+    // every accepted item has been popped before the queue reported Closed
+    assert_eq!(pushed.load(Ordering::SeqCst), popped.load(Ordering::SeqCst));
+    assert!(q.is_empty());
+}
+
+#[test]
+fn force_push_evicts_once() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 4;
+
+    let q: InlineQueue<usize> = InlineQueue::new(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for _ in 0..COUNT {
+                if let Some(n) = q.pop() {
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    if let Some(n) = q.force_push(i).unwrap() {
+                        v[n].fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+        }
+    });
+
+    // every item has been either popped, evicted or is still queued, exactly once
+    for n in q {
+        v[n].fetch_add(1, Ordering::SeqCst);
+    }
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn batch() {
+    let q = InlineQueue::new(4);
+
+    // batches of 3 start at every offset of the buffer and wrap around
+    for round in 0..8 {
+        let mut items = 3 * round..3 * round + 3;
+        assert_eq!(q.push_iter(&mut items), (3, None));
+        assert_eq!(q.pop_n(2), [3 * round, 3 * round + 1]);
+        let mut out = Vec::new();
+        assert_eq!(q.pop_into(&mut out, 4), 1);
+        assert_eq!(out, [3 * round + 2]);
+    }
+
+    // the item, which did not fit, is handed back and the rest is left in the iterator
+    let mut items = 0..6;
+    assert_eq!(q.push_iter(&mut items), (4, Some(PushError::Full(4))));
+    assert_eq!(items.next(), Some(5));
+    assert_eq!(q.pop_n(10), [0, 1, 2, 3]);
+
+    assert_eq!(q.push_slice(&[1, 2, 3, 4, 5]), 4);
+    assert_eq!(q.pop_n(10), [1, 2, 3, 4]);
+    assert!(q.is_empty());
+}
+
+#[test]
+fn batch_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 64;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+    const BATCH: usize = 8;
+
+    let q: InlineQueue<usize> = InlineQueue::new(20);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut buf = Vec::with_capacity(BATCH);
+                while popped.load(Ordering::SeqCst) < COUNT * THREADS {
+                    buf.clear();
+                    let n = q.pop_into(&mut buf, BATCH);
+                    if n == 0 {
+                        yield_now();
+                    }
+                    for &i in &buf {
+                        v[i].fetch_add(1, Ordering::SeqCst);
+                    }
+                    popped.fetch_add(n, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut items = (0..COUNT).peekable();
+                while items.peek().is_some() {
+                    let (_, rejected) = q.push_iter(&mut items.by_ref().take(BATCH));
+                    if let Some(PushError::Full(i)) = rejected {
+                        while q.push(i).is_err() {
+                            yield_now();
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn into_iter() {
+    let q = InlineQueue::new(100);
+    for i in 0..100 {
+        q.push(i).unwrap();
+    }
+    for (i, j) in q.into_iter().enumerate() {
+        assert_eq!(i, j);
+    }
+}
+
+#[test]
+fn padded() {
+    let q: InlineQueue<usize, Padded> = InlineQueue::with_layout(3);
+    for round in 0..10 {
+        for i in 0..3 {
+            q.push(round + i).unwrap();
+        }
+        assert_eq!(q.push(0), Err(PushError::Full(0)));
+        for i in 0..3 {
+            assert_eq!(q.pop(), Some(round + i));
+        }
+        assert!(q.is_empty());
+    }
+}
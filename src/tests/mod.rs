@@ -1,3 +1,20 @@
 #[cfg(feature = "alloc")]
 mod arrayqueue;
+#[cfg(feature = "alloc")]
+mod async_channel;
+#[cfg(feature = "std")]
+mod backoff;
+mod borrow;
+#[cfg(feature = "std")]
+mod channel;
 mod heapless;
+#[cfg(feature = "alloc")]
+mod index;
+#[cfg(feature = "alloc")]
+mod inline;
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "alloc")]
+mod split;
+#[cfg(feature = "alloc")]
+mod unbounded;
@@ -0,0 +1,130 @@
+use std::{boxed::Box, ptr::NonNull, sync::Arc, thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{ArcQueue, HeapBackedQueue, PopError, PushError, RawQueue};
+
+#[test]
+fn push_box() {
+    let q = HeapBackedQueue::new(2);
+
+    let item = Box::new(1);
+    let ptr = &*item as *const i32;
+    q.push_box(item).unwrap();
+    q.push(2).unwrap();
+    assert_eq!(q.push_box(Box::new(3)), Err(PushError::Full(Box::new(3))));
+
+    // the box is handed back without moving the item
+    let item = q.pop_box().unwrap();
+    assert_eq!(&*item as *const i32, ptr);
+    assert_eq!(*item, 1);
+    // pool boxes can be taken out as well
+    assert_eq!(q.pop_box(), Some(Box::new(2)));
+    assert!(q.pop_box().is_none());
+
+    // a pushed box is recycled by pop
+    q.push_box(Box::new(4)).unwrap();
+    assert_eq!(q.pop(), Some(4));
+
+    q.close();
+    assert_eq!(q.push_box(Box::new(5)), Err(PushError::Closed(Box::new(5))));
+}
+
+#[test]
+fn arc_refcount() {
+    let q = ArcQueue::new(2);
+    let item = Arc::new(1);
+
+    q.push(item.clone()).unwrap();
+    q.push(item.clone()).unwrap();
+    assert_eq!(Arc::strong_count(&item), 3);
+
+    let rejected = q.push(item.clone()).unwrap_err().into_inner();
+    assert!(Arc::ptr_eq(&rejected, &item));
+    drop(rejected);
+    assert_eq!(Arc::strong_count(&item), 3);
+
+    let popped = q.pop().unwrap();
+    assert!(Arc::ptr_eq(&popped, &item));
+    drop(popped);
+    assert_eq!(Arc::strong_count(&item), 2);
+
+    // the remaining reference is released with the queue
+    drop(q);
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn arc_force_push() {
+    let q = ArcQueue::new(1);
+    q.push(Arc::new(1)).unwrap();
+
+    let displaced = q.force_push(Arc::new(2)).unwrap().unwrap();
+    assert_eq!(*displaced, 1);
+    assert_eq!(
+        q.pop_n(2).iter().map(|item| **item).collect::<Vec<_>>(),
+        [2]
+    );
+
+    q.close();
+    assert_eq!(*q.force_push(Arc::new(3)).unwrap_err(), 3);
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+}
+
+#[test]
+fn arc_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 2;
+
+    let q = ArcQueue::new(3);
+    let item = Arc::new(42);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let popped = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    assert!(Arc::ptr_eq(&popped, &item));
+                }
+            });
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let mut next = item.clone();
+                    while let Err(err) = q.push(next) {
+                        next = err.into_inner();
+                        yield_now();
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+#[test]
+fn raw() {
+    let q = RawQueue::new(2);
+    let items = (0..3)
+        .map(|i| NonNull::from(Box::leak(Box::new(i))))
+        .collect::<Vec<_>>();
+
+    let (pushed, rejected) = q.push_iter(&mut items.iter().copied());
+    assert_eq!(pushed, 2);
+    assert_eq!(rejected, Some(PushError::Full(items[2])));
+
+    assert_eq!(q.pop(), Some(items[0]));
+    assert_eq!(q.force_push(items[2]), Ok(None));
+    assert_eq!(q.pop_n(2), [items[1], items[2]]);
+    assert!(q.pop().is_none());
+
+    for item in items {
+        drop(unsafe { Box::from_raw(item.as_ptr()) });
+    }
+}
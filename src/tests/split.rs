@@ -0,0 +1,157 @@
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{
+    HeapBackedQueue, PopError, PushError,
+    split::{Multi, Single},
+};
+
+#[test]
+fn spsc() {
+    let (tx, rx) = HeapBackedQueue::new(3).split::<Single, Single>();
+
+    // several rounds, so the cursors wrap around
+    for round in 0..4 {
+        for i in 0..3 {
+            tx.push(round * 3 + i).unwrap();
+        }
+        assert_eq!(tx.push(-1), Err(PushError::Full(-1)));
+        assert!(tx.is_full());
+        for i in 0..3 {
+            assert_eq!(rx.pop(), Some(round * 3 + i));
+        }
+        assert_eq!(rx.pop(), None);
+    }
+
+    // interleaved
+    tx.push(1).unwrap();
+    tx.push(2).unwrap();
+    assert_eq!(rx.pop(), Some(1));
+    tx.push(3).unwrap();
+    tx.push(4).unwrap();
+    assert_eq!(rx.pop(), Some(2));
+    assert_eq!(rx.pop(), Some(3));
+    assert_eq!(rx.pop(), Some(4));
+    assert!(rx.is_empty());
+}
+
+#[test]
+fn split_used_queue() {
+    let q = HeapBackedQueue::new(3);
+    for i in 0..5 {
+        q.push(i).unwrap();
+        if i % 2 == 0 {
+            q.pop().unwrap();
+        }
+    }
+    // the queue holds [3, 4] and its cells are in different rounds
+    let (tx, rx) = q.split::<Single, Single>();
+    assert_eq!(tx.len(), 2);
+
+    tx.push(5).unwrap();
+    assert_eq!(tx.push(6), Err(PushError::Full(6)));
+    assert_eq!(rx.pop(), Some(3));
+    tx.push(6).unwrap();
+    for i in 4..7 {
+        assert_eq!(rx.pop(), Some(i));
+    }
+    assert_eq!(rx.pop(), None);
+}
+
+#[test]
+fn close() {
+    let (tx, rx) = HeapBackedQueue::new(2).split::<Single, Single>();
+    tx.push(1).unwrap();
+    assert!(rx.close());
+
+    assert!(tx.is_closed());
+    assert_eq!(tx.push(2), Err(PushError::Closed(2)));
+    assert_eq!(rx.try_pop(), Ok(1));
+    assert_eq!(rx.try_pop(), Err(PopError::Closed));
+}
+
+#[test]
+fn mpsc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 3;
+
+    let (tx, rx) = HeapBackedQueue::new(4).split::<Multi, Single>();
+
+    scope(|scope| {
+        for id in 0..THREADS {
+            let tx = tx.clone();
+            scope.spawn(move || {
+                for i in 0..COUNT {
+                    let mut next = (id, i);
+                    while let Err(err) = tx.push(next) {
+                        next = err.into_inner();
+                        yield_now();
+                    }
+                }
+            });
+        }
+        scope.spawn(move || {
+            // the items of every producer arrive in order
+            let mut expected = [0; THREADS];
+            for _ in 0..COUNT * THREADS {
+                let (id, i) = loop {
+                    if let Some(item) = rx.pop() {
+                        break item;
+                    }
+                    yield_now();
+                };
+                assert_eq!(i, expected[id]);
+                expected[id] += 1;
+            }
+            assert_eq!(rx.pop(), None);
+        });
+    });
+}
+
+#[test]
+fn spmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 3;
+
+    let (tx, rx) = HeapBackedQueue::new(4).split::<Single, Multi>();
+
+    let popped = scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..COUNT * THREADS {
+                let mut next = i;
+                while let Err(err) = tx.push(next) {
+                    next = err.into_inner();
+                    yield_now();
+                }
+            }
+        });
+        let consumers = (0..THREADS)
+            .map(|_| {
+                let rx = rx.clone();
+                scope.spawn(move || {
+                    let mut popped = Vec::with_capacity(COUNT);
+                    while popped.len() < COUNT {
+                        match rx.pop() {
+                            Some(item) => popped.push(item),
+                            None => yield_now(),
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect::<Vec<_>>();
+        consumers
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut popped = popped;
+    popped.sort_unstable();
+    assert_eq!(popped, (0..COUNT * THREADS).collect::<Vec<_>>());
+}
@@ -0,0 +1,243 @@
+//! Testing for nblfq queue
+//!
+//! Tests adapted from crossbeam-queue's test suite.
+//! https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-queue
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread::scope, thread::yield_now, vec::Vec};
+
+use crate::{PopError, UnboundedQueue};
+
+#[test]
+fn smoke() {
+    let q = UnboundedQueue::new();
+    q.push(7).unwrap();
+    assert_eq!(q.pop(), Some(7));
+
+    q.push(8).unwrap();
+    assert_eq!(q.pop(), Some(8));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn is_empty() {
+    let q = UnboundedQueue::with_segment_size(2);
+    assert!(q.is_empty());
+
+    for i in 0..5 {
+        q.push(i).unwrap();
+        assert!(!q.is_empty());
+    }
+    for _ in 0..5 {
+        assert!(!q.is_empty());
+        q.pop().unwrap();
+    }
+    assert!(q.is_empty());
+}
+
+#[test]
+fn segments() {
+    #[cfg(miri)]
+    const COUNT: usize = 100;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+
+    let q = UnboundedQueue::with_segment_size(3);
+
+    for round in 0..3 {
+        for i in 0..COUNT {
+            q.push(i + round).unwrap();
+        }
+        for i in 0..COUNT {
+            assert_eq!(q.pop(), Some(i + round));
+        }
+        assert!(q.pop().is_none());
+    }
+}
+
+#[test]
+fn spsc() {
+    #[cfg(miri)]
+    const COUNT: usize = 100;
+    #[cfg(not(miri))]
+    const COUNT: usize = 100_000;
+
+    let q = UnboundedQueue::with_segment_size(3);
+
+    scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                loop {
+                    if let Some(x) = q.pop() {
+                        assert_eq!(x, i);
+                        break;
+                    }
+                    yield_now();
+                }
+            }
+            assert!(q.pop().is_none());
+        });
+
+        scope.spawn(|| {
+            for i in 0..COUNT {
+                q.push(i).unwrap();
+            }
+        });
+    })
+}
+
+#[test]
+fn mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 25_000;
+    const THREADS: usize = 4;
+
+    let q: UnboundedQueue<usize> = UnboundedQueue::with_segment_size(3);
+    let v = (0..COUNT).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..COUNT {
+                    let n = loop {
+                        if let Some(x) = q.pop() {
+                            break x;
+                        }
+                        yield_now();
+                    };
+                    v[n].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    q.push(i).unwrap();
+                }
+            });
+        }
+    });
+
+    for c in v {
+        assert_eq!(c.load(Ordering::SeqCst), THREADS);
+    }
+}
+
+#[test]
+fn drops() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq)]
+    struct DropCounter;
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let q = UnboundedQueue::with_segment_size(4);
+    for _ in 0..30 {
+        q.push(DropCounter).unwrap();
+    }
+    for _ in 0..10 {
+        drop(q.pop().unwrap());
+    }
+    assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+
+    drop(q);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 30);
+}
+
+#[test]
+fn close() {
+    let q = UnboundedQueue::with_segment_size(1);
+    q.push(1).unwrap();
+    q.push(2).unwrap();
+    assert!(!q.is_closed());
+
+    assert!(q.close());
+    assert!(!q.close());
+    assert!(q.is_closed());
+    assert_eq!(q.push(3), Err(3));
+
+    // remaining items are still popped, across segments
+    assert_eq!(q.try_pop(), Ok(1));
+    assert_eq!(q.try_pop(), Ok(2));
+    assert_eq!(q.try_pop(), Err(PopError::Closed));
+    assert!(q.pop().is_none());
+}
+
+#[test]
+fn close_mpmc() {
+    #[cfg(miri)]
+    const COUNT: usize = 50;
+    #[cfg(not(miri))]
+    const COUNT: usize = 10_000;
+    const THREADS: usize = 4;
+
+    let q = UnboundedQueue::with_segment_size(3);
+    let pushed = AtomicUsize::new(0);
+    let popped = AtomicUsize::new(0);
+
+    scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                loop {
+                    match q.try_pop() {
+                        Ok(_) => {
+                            popped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(PopError::Empty) => yield_now(),
+                        Err(PopError::Closed) => break,
+                    }
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    if q.push(i).is_err() {
+                        return;
+                    }
+                    pushed.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        scope.spawn(|| {
+            while pushed.load(Ordering::SeqCst) < COUNT {
+                yield_now();
+            }
+            q.close();
+        });
+    });
+
+    // every accepted item has been popped before the queue reported Closed
+    assert_eq!(pushed.load(Ordering::SeqCst), popped.load(Ordering::SeqCst));
+    assert!(q.is_empty());
+}
+
+#[test]
+fn into_iter() {
+    let q = UnboundedQueue::with_segment_size(7);
+    for i in 0..100 {
+        q.push(i).unwrap();
+    }
+    for (i, j) in q.into_iter().enumerate() {
+        assert_eq!(i, j);
+    }
+}
+
+#[test]
+fn many_polls() {
+    // every poll acquires a reference through the head word
+    let q = UnboundedQueue::<usize>::with_segment_size(2);
+    for _ in 0..100_000 {
+        assert!(q.pop().is_none());
+        assert!(q.is_empty());
+    }
+    q.push(1).unwrap();
+    assert_eq!(q.pop(), Some(1));
+}
@@ -0,0 +1,416 @@
+use alloc::boxed::Box;
+use core::{
+    fmt::Debug,
+    hint::spin_loop,
+    iter,
+    ptr::{null_mut, without_provenance_mut},
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::{
+    arrayqueue::ArrayQueue,
+    components::{FixedBuf, Item},
+    error::{PopError, PushError},
+    pool::BoxPool,
+};
+
+/// The default number of cells per segment.
+const SEGMENT_SIZE: usize = 32;
+
+/// The reference count every segment holds for each of the queue's head and tail words.
+///
+/// A segment is installed in both words over its lifetime. While it is installed in at least one of them,
+/// its reference count stays above `LINK - 4 * TRANSFER`, so only the release of the last reference can reach 0.
+const LINK: usize = 1 << (usize::BITS - 3);
+
+/// Installed as `next` of the last segment, once the queue has been closed.
+///
+/// The address is not aligned for a segment, so it never collides with a real one.
+fn closed<T>() -> *mut Segment<T> {
+    without_provenance_mut(1)
+}
+
+/// A ring segment of the queue.
+///
+/// # Reclamation
+///
+/// Segments are reference counted with split reference counts:
+/// the head and tail words store a (count, ptr) pair, where count is the number of references acquired through that word.
+/// When a word moves on to the next segment, its count is transferred to the segment's `refs`,
+/// together with the release of the word's `LINK`.
+/// Each acquired reference is released by decrementing `refs`, the segment is freed once `refs` reaches 0.
+struct Segment<T> {
+    ring: ArrayQueue<T, FixedBuf<T>>,
+    /// The following segment, or `closed()`.
+    next: AtomicPtr<Segment<T>>,
+    refs: AtomicUsize,
+}
+
+impl<T> Segment<T> {
+    fn new(size: usize) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            ring: ArrayQueue::new_in(FixedBuf::new(size)),
+            next: AtomicPtr::new(null_mut()),
+            refs: AtomicUsize::new(2 * LINK),
+        }))
+    }
+}
+
+/// Once this many references have been acquired through a word, they are transferred to the segment's `refs`.
+///
+/// This is at most half of the count field and a quarter of `LINK`, which is the smaller bound on 32-bit targets.
+const TRANSFER: u64 = {
+    let half = Item::<()>::MAX_W / 2;
+    let link = (LINK / 4) as u64;
+    if half < link { half } else { link }
+};
+
+/// Acquires a reference to the segment stored in `word`.
+fn acquire<T>(word: &Item<Segment<T>>) -> *const Segment<T> {
+    loop {
+        let (count, segment) = word.components();
+        if count >= 2 * TRANSFER - 1 {
+            // the count is saturated, wait for a transfer
+            spin_loop();
+            continue;
+        }
+        if word.cmpxchg(segment, count, segment, count + 1).is_ok() {
+            if count + 1 >= TRANSFER {
+                unsafe { transfer(word, segment) };
+            }
+            return segment;
+        }
+    }
+}
+
+/// Moves the references acquired through `word` to the segment's `refs` and resets the word's count,
+/// so that the count does not overflow while the word points to the same segment.
+///
+/// # Safety
+///
+/// The caller must hold a reference to `segment`.
+unsafe fn transfer<T>(word: &Item<Segment<T>>, segment: *const Segment<T>) {
+    let refs = &unsafe { &*segment }.refs;
+    loop {
+        let (count, current) = word.components();
+        if current != segment || count < TRANSFER {
+            // the word has moved on or another thread has transferred the count
+            return;
+        }
+        // add the references before they are dropped from the word, so refs never undercounts
+        refs.fetch_add(count as usize, Ordering::AcqRel);
+        if word.cmpxchg(segment, count, segment, 0).is_ok() {
+            return;
+        }
+        // the caller's reference keeps refs above 0
+        refs.fetch_sub(count as usize, Ordering::AcqRel);
+    }
+}
+
+/// Releases a reference acquired through `acquire`.
+///
+/// # Safety
+///
+/// The reference must not be used afterwards.
+unsafe fn release<T>(segment: *const Segment<T>) {
+    if unsafe { &*segment }.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+        drop(unsafe { Box::from_raw(segment as *mut Segment<T>) });
+    }
+}
+
+/// Moves `word` from `from` to `to`, if it still points to `from`.
+///
+/// # Safety
+///
+/// The caller must hold a reference to `from`.
+unsafe fn swing<T>(word: &Item<Segment<T>>, from: *const Segment<T>, to: *const Segment<T>) {
+    loop {
+        let (count, segment) = word.components();
+        if segment != from {
+            // another thread has already moved on
+            return;
+        }
+        if word.cmpxchg(from, count, to, 0).is_ok() {
+            // transfer the acquired references and drop the word's link
+            let delta = LINK - count as usize;
+            // the caller's reference keeps refs above 0
+            unsafe { &*from }.refs.fetch_sub(delta, Ordering::AcqRel);
+            return;
+        }
+    }
+}
+
+/// An unbounded MPMC queue.
+///
+/// The queue is a linked list of NBLFQ ring segments.
+/// Once a segment is full, producers close it and append a new one,
+/// consumers move on to the next segment once a closed segment is drained.
+/// Retired segments are freed as soon as no thread holds a reference to them anymore.
+pub struct UnboundedQueue<T> {
+    /// The segment consumers pop from.
+    head: Item<Segment<T>>,
+    /// The segment producers push to.
+    tail: Item<Segment<T>>,
+    segment_size: usize,
+    pool: BoxPool<T>,
+}
+
+impl<T> UnboundedQueue<T> {
+    pub fn new() -> Self {
+        Self::with_segment_size(SEGMENT_SIZE)
+    }
+
+    /// Creates a queue, which allocates its ring segments with `size` cells each.
+    pub fn with_segment_size(size: usize) -> Self {
+        assert!(size > 0, "Size of a segment must be greater than 0");
+        let segment = Segment::new(size);
+        Self {
+            head: Item::<Segment<T>>::from_components(0, segment),
+            tail: Item::<Segment<T>>::from_components(0, segment),
+            segment_size: size,
+            pool: BoxPool::new_in(size, 2 * size, alloc::alloc::Global),
+        }
+    }
+
+    /// Pushes an item into the queue.
+    /// Returns the item as an error if the queue is closed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::UnboundedQueue;
+    ///
+    /// let q = UnboundedQueue::with_segment_size(1);
+    ///
+    /// assert_eq!(q.push(10), Ok(()));
+    /// assert_eq!(q.push(20), Ok(()));
+    /// assert_eq!(q.pop(), Some(10));
+    /// ```
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let item = self.pool.boxed(item);
+        loop {
+            let segment_ptr = acquire(&self.tail);
+            let segment = unsafe { &*segment_ptr };
+
+            match segment.ring.push(item) {
+                Ok(()) => {
+                    unsafe { release(segment_ptr) };
+                    return Ok(());
+                }
+                Err(PushError::Full(_)) => {
+                    segment.ring.close();
+                }
+                Err(PushError::Closed(_)) => {}
+            }
+
+            // the segment is closed, append a new one or help installing it
+            let mut next = segment.next.load(Ordering::Acquire);
+            if next.is_null() {
+                let new = Segment::new(self.segment_size);
+                let pushed = unsafe { &*new }.ring.push(item);
+                debug_assert!(pushed.is_ok(), "fresh segment is full");
+                match segment.next.compare_exchange(
+                    null_mut(),
+                    new,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => {
+                        unsafe { swing(&self.tail, segment_ptr, new) };
+                        unsafe { release(segment_ptr) };
+                        return Ok(());
+                    }
+                    Err(actual) => {
+                        // another producer appended first, retry there
+                        let new = unsafe { Box::from_raw(new) };
+                        let popped = new.ring.pop();
+                        debug_assert_eq!(popped, Some(item));
+                        next = actual;
+                    }
+                }
+            }
+            if next == closed() {
+                unsafe { release(segment_ptr) };
+                return Err(unsafe { self.pool.unboxed(item) });
+            }
+            unsafe { swing(&self.tail, segment_ptr, next) };
+            unsafe { release(segment_ptr) };
+        }
+    }
+
+    /// pop the last item, if an item is contained
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::UnboundedQueue;
+    ///
+    /// let q = UnboundedQueue::new();
+    /// q.push(10).unwrap();
+    ///
+    /// assert_eq!(q.pop(), Some(10));
+    /// assert!(q.pop().is_none());
+    /// ```
+    pub fn pop(&self) -> Option<T> {
+        self.try_pop().ok()
+    }
+
+    /// Attempts to pop an item.
+    /// Unlike `pop`, this tells an empty queue apart from a closed queue, which has been drained.
+    /// While a push into the closed queue is still pending, this reports `PopError::Empty` rather than waiting for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nblfq::{PopError, UnboundedQueue};
+    ///
+    /// let q = UnboundedQueue::new();
+    /// assert_eq!(q.try_pop(), Err(PopError::Empty));
+    ///
+    /// q.push(10).unwrap();
+    /// q.close();
+    /// assert_eq!(q.try_pop(), Ok(10));
+    /// assert_eq!(q.try_pop(), Err(PopError::Closed));
+    /// ```
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        loop {
+            let segment_ptr = acquire(&self.head);
+            let segment = unsafe { &*segment_ptr };
+
+            let mut item = segment.ring.pop();
+            let next = segment.next.load(Ordering::Acquire);
+            if item.is_none() && !next.is_null() {
+                // the segment has been closed, it is drained once all pending pushes are done
+                if segment.ring.has_pending_pushes() {
+                    unsafe { release(segment_ptr) };
+                    return Err(PopError::Empty);
+                }
+                item = segment.ring.pop();
+                if item.is_none() {
+                    if next == closed() {
+                        unsafe { release(segment_ptr) };
+                        return Err(PopError::Closed);
+                    }
+                    unsafe { swing(&self.head, segment_ptr, next) };
+                    unsafe { release(segment_ptr) };
+                    continue;
+                }
+            }
+
+            unsafe { release(segment_ptr) };
+            return item
+                .map(|item| unsafe { self.pool.unboxed(item) })
+                .ok_or(PopError::Empty);
+        }
+    }
+
+    /// Closes the queue.
+    /// Subsequent pushes fail, while the remaining items can still be popped.
+    /// Returns false if the queue has already been closed.
+    pub fn close(&self) -> bool {
+        loop {
+            let segment_ptr = acquire(&self.tail);
+            let segment = unsafe { &*segment_ptr };
+            segment.ring.close();
+
+            let next = match segment.next.compare_exchange(
+                null_mut(),
+                closed(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    unsafe { release(segment_ptr) };
+                    return true;
+                }
+                Err(next) => next,
+            };
+            if next == closed() {
+                unsafe { release(segment_ptr) };
+                return false;
+            }
+            // a segment has been appended in the meantime, close that one instead
+            unsafe { swing(&self.tail, segment_ptr, next) };
+            unsafe { release(segment_ptr) };
+        }
+    }
+
+    /// Indicates whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        loop {
+            let segment_ptr = acquire(&self.tail);
+            let next = unsafe { &*segment_ptr }.next.load(Ordering::Acquire);
+            if next.is_null() || next == closed() {
+                unsafe { release(segment_ptr) };
+                return !next.is_null();
+            }
+            unsafe { swing(&self.tail, segment_ptr, next) };
+            unsafe { release(segment_ptr) };
+        }
+    }
+
+    /// Indicates whether the queue is empty.
+    /// The result may be stale.
+    pub fn is_empty(&self) -> bool {
+        let segment_ptr = acquire(&self.head);
+        let segment = unsafe { &*segment_ptr };
+        let next = segment.next.load(Ordering::Acquire);
+        let empty = segment.ring.is_empty() && (next.is_null() || next == closed());
+        unsafe { release(segment_ptr) };
+        empty
+    }
+}
+
+impl<T> Default for UnboundedQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for UnboundedQueue<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad("UnboundedQueue { ... }")
+    }
+}
+
+impl<T> Drop for UnboundedQueue<T> {
+    fn drop(&mut self) {
+        // move the tail to the last segment, so that every live segment is reachable from the head
+        loop {
+            let tail = acquire(&self.tail);
+            let next = unsafe { &*tail }.next.load(Ordering::Acquire);
+            let last = next.is_null() || next == closed();
+            if !last {
+                unsafe { swing(&self.tail, tail, next) };
+            }
+            unsafe { release(tail) };
+            if last {
+                break;
+            }
+        }
+
+        let (_, mut segment) = self.head.components();
+        while !segment.is_null() && segment != closed::<T>().cast_const() {
+            let current = unsafe { Box::from_raw(segment as *mut Segment<T>) };
+            // drop all remaining items
+            while let Some(item) = current.ring.pop() {
+                drop(unsafe { self.pool.unboxed(item) });
+            }
+            segment = current.next.load(Ordering::Acquire);
+        }
+    }
+}
+
+impl<T> IntoIterator for UnboundedQueue<T> {
+    type Item = T;
+    type IntoIter = impl Iterator<Item = Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        iter::from_fn(move || self.pop())
+    }
+}
+
+/// Safety: UnboundedQueue sends owned T's between threads.
+/// It is only safe to do so, if T is Send
+unsafe impl<T: Send> Sync for UnboundedQueue<T> {}
+unsafe impl<T: Send> Send for UnboundedQueue<T> {}
@@ -1,4 +1,4 @@
-use cfg_if::cfg_if;
+use core::ops::Deref;
 
 pub(crate) fn prev(i: usize, size: usize) -> usize {
     (i + size - 1) % size
@@ -12,15 +12,39 @@ pub(crate) fn comp(i: usize, u: u64, j: usize, v: u64, w_max: u64) -> bool {
     }
 }
 
-cfg_if! {
-    if #[cfg(not(feature = "no-tagged-ptr"))] {
-        pub(crate) use tagged_ptr::*;
-    } else {
-        pub(crate) use dword::*;
+/// Aligns and pads a value to the size of a cache line, so it never shares a line with its neighbours.
+///
+/// x86_64 and aarch64 prefetch cache lines in pairs, so 128 bytes are used there.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
     }
 }
 
-#[cfg(feature = "no-tagged-ptr")]
+#[cfg(target_pointer_width = "64")]
+pub(crate) use dword::*;
+pub(crate) use dword32::*;
+#[cfg(target_pointer_width = "64")]
+pub(crate) use tagged_ptr::*;
+
+#[cfg(target_pointer_width = "64")]
 mod dword {
 
     // dword ptr 128bit:
@@ -38,33 +62,102 @@ mod dword {
     }
 }
 
-#[cfg(not(feature = "no-tagged-ptr"))]
+mod dword32 {
+
+    // dword ptr on 32-bit targets, or an index encoded as ptr (storage `Index`), 64bit:
+    // |----32 bit----|----32 bit----|
+    //       count    |     ptr
+
+    pub(crate) fn components_as_dword32<T>(count: u64, ptr: *const T) -> u64 {
+        debug_assert!(
+            count <= u32::MAX as u64,
+            "Count too large for the count field"
+        );
+        debug_assert!(
+            ptr as usize as u64 <= u32::MAX as u64,
+            "Pointer too large for the pointer field"
+        );
+        (count << 32) | (ptr as usize as u64)
+    }
+
+    pub(crate) fn components_from_dword32<T>(dword: u64) -> (u64, *const T) {
+        let count = dword >> 32;
+        let ptr = dword as u32 as usize as *const T;
+        (count, ptr)
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
 mod tagged_ptr {
 
     // tagged ptr 64bit:
     // |--16 bit--|----48 bit----|
     //    count   |     ptr
+    //
+    // with 5-level paging (feature `la57`):
+    // |--7 bit--|----57 bit----|
+    //    count  |     ptr
+
+    /// The number of significant bits of a virtual address.
+    #[cfg(not(feature = "la57"))]
+    pub(crate) const PTR_BITS: u32 = 48;
+    #[cfg(feature = "la57")]
+    pub(crate) const PTR_BITS: u32 = 57;
+
+    /// The number of distinct counts, which fit above the pointer.
+    pub(crate) const COUNT_RANGE: u64 = 1 << (u64::BITS - PTR_BITS);
+
+    const PTR_MASK: u64 = (1u64 << PTR_BITS) - 1;
 
     pub(crate) fn components_as_tagged<T>(count: u64, ptr: *const T) -> u64 {
-        debug_assert!(count <= u16::MAX as u64, "Count too large for 16-bit field");
-        let ptr_non_extended = ptr as u64 & ((1u64 << 48) - 1);
-        (count << 48) | ptr_non_extended
+        debug_assert!(count < COUNT_RANGE, "Count too large for the count field");
+        // a pointer outside of the address range would silently be corrupted,
+        // e.g. a heap pointer above 48 bits with 5-level paging enabled.
+        // it fits, if the bits above the pointer copy its top bit
+        let high = ptr as i64 >> (PTR_BITS - 1);
+        assert!(
+            high == 0 || high == -1,
+            "pointer {ptr:p} does not fit into {PTR_BITS} bits, consider enabling the `la57` feature",
+        );
+        (count << PTR_BITS) | (ptr as u64 & PTR_MASK)
     }
 
     pub(crate) fn components_from_tagged<T>(ptr: u64) -> (u64, *const T) {
-        let count = ptr >> 48;
-        let ptr_mask = (1u64 << 48) - 1;
-        let raw_ptr = ptr & ptr_mask;
+        let count = ptr >> PTR_BITS;
+        let raw_ptr = ptr & PTR_MASK;
         (count, sign_extend(raw_ptr) as *const T)
     }
 
     fn sign_extend(ptr: u64) -> u64 {
-        if ptr & (1u64 << 47) != 0 {
-            ptr | (!((1u64 << 48) - 1))
+        if ptr & (1u64 << (PTR_BITS - 1)) != 0 {
+            ptr | !PTR_MASK
         } else {
             ptr
         }
     }
+
+    // aligned tagged ptr 64bit (storage `AlignTagged`), for a T aligned to 2^k bytes:
+    // |--16 bit--|----48 - k bit----|--k bit--|
+    //  count hi  |       ptr        | count lo
+
+    /// The number of low bits, which are 0 in every pointer to T.
+    pub(crate) const fn align_bits<T>() -> u32 {
+        core::mem::align_of::<T>().trailing_zeros()
+    }
+
+    pub(crate) fn components_as_aligned<T>(count: u64, ptr: *const T) -> u64 {
+        let bits = align_bits::<T>();
+        let low = (1u64 << bits) - 1;
+        assert_eq!(ptr as u64 & low, 0, "pointer {ptr:p} is not aligned");
+        components_as_tagged(count >> bits, ptr) | (count & low)
+    }
+
+    pub(crate) fn components_from_aligned<T>(tagged: u64) -> (u64, *const T) {
+        let bits = align_bits::<T>();
+        let low = (1u64 << bits) - 1;
+        let (count, ptr) = components_from_tagged(tagged & !low);
+        ((count << bits) | (tagged & low), ptr)
+    }
 }
 
 #[cfg(test)]
@@ -72,7 +165,7 @@ mod tests {
     use super::*;
     use core::ptr::null;
 
-    #[cfg(not(feature = "no-tagged-ptr"))]
+    #[cfg(all(target_pointer_width = "64", not(feature = "la57")))]
     mod tagged_ptr {
         use super::*;
 
@@ -130,9 +223,89 @@ mod tests {
             assert_eq!(count, count_);
             assert_eq!(*data, unsafe { *data_ });
         }
+
+        #[test]
+        #[should_panic]
+        fn pointer_out_of_range() {
+            // bit 48 is set, which would be lost
+            components_as_tagged(0, 0x0001_0000_DEAD_BEEF as *const u8);
+        }
     }
 
-    #[cfg(feature = "no-tagged-ptr")]
+    #[cfg(all(target_pointer_width = "64", feature = "la57"))]
+    mod tagged_ptr_la57 {
+        use super::*;
+
+        #[test]
+        fn tagged() {
+            let ptr = u64::MAX as *const u8;
+            let ptr2 = 0x00AB_CDEF_DEAD_BEEF as *const u8;
+            let count = 0x5A;
+
+            assert_eq!(components_as_tagged(count, ptr2), 0xB4AB_CDEF_DEAD_BEEF);
+            assert_eq!(
+                components_from_tagged(components_as_tagged(count, ptr)),
+                (count, ptr)
+            );
+            assert_eq!(
+                components_from_tagged(components_as_tagged(count, ptr2)),
+                (count, ptr2)
+            );
+
+            let ptr: *const u8 = null();
+            assert_eq!(components_as_tagged(0, ptr), 0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn pointer_out_of_range() {
+            // bit 57 is set, which would be lost
+            components_as_tagged(0, 0x0200_0000_DEAD_BEEF as *const u8);
+        }
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    mod aligned_tagged_ptr {
+        use super::*;
+
+        #[test]
+        fn aligned() {
+            assert_eq!(align_bits::<u64>(), 3);
+            assert_eq!(align_bits::<u8>(), 0);
+
+            // the low 3 bits of the count are stored below the pointer
+            let ptr = 0xDEAD_BEE8 as *const u64;
+            let count = 0b101_0110;
+            let res = components_as_aligned(count, ptr);
+            assert_eq!(res, components_as_tagged(0b1010, ptr) | 0b110);
+            assert_eq!(components_from_aligned(res), (count, ptr));
+
+            let ptr = u64::MAX as *const u64;
+            let ptr = ptr.wrapping_byte_sub(7);
+            let count = (COUNT_RANGE << 3) - 1;
+            assert_eq!(
+                components_from_aligned(components_as_aligned(count, ptr)),
+                (count, ptr)
+            );
+
+            // without alignment bits, this is the plain tagged encoding
+            let ptr = 0xDEAD_BEEF as *const u8;
+            assert_eq!(
+                components_as_aligned(42, ptr),
+                components_as_tagged(42, ptr)
+            );
+            let ptr: *const u64 = null();
+            assert_eq!(components_as_aligned(0, ptr), 0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn unaligned_pointer() {
+            components_as_aligned(0, 0xDEAD_BEEF as *const u64);
+        }
+    }
+
+    #[cfg(target_pointer_width = "64")]
     mod dword {
         use super::*;
 
@@ -192,6 +365,47 @@ mod tests {
         }
     }
 
+    mod dword32 {
+        use super::*;
+
+        #[test]
+        fn into_dword32() {
+            let ptr = u32::MAX as usize as *const u8;
+            let count = 0xDEAD_BEEF;
+            let res = components_as_dword32(count, ptr);
+            assert_eq!(res, 0xDEAD_BEEF_FFFF_FFFF);
+
+            let ptr2 = 0xBEEF as *const u8;
+            let res = components_as_dword32(count, ptr2);
+            assert_eq!(res, 0xDEAD_BEEF_0000_BEEF);
+
+            let ptr: *const u8 = null();
+            assert_eq!(components_as_dword32(0, ptr), 0);
+        }
+
+        #[test]
+        fn dword32() {
+            let ptr = u32::MAX as usize as *const u8;
+            let count = u32::MAX as u64;
+
+            assert_eq!(
+                components_from_dword32(components_as_dword32(count, ptr)),
+                (count, ptr)
+            );
+        }
+
+        #[test]
+        #[cfg(target_pointer_width = "32")]
+        fn dword32_ptr() {
+            let data = &4242;
+            let count = 42;
+            let dword = components_as_dword32(count, data as *const i32);
+            let (count_, data_): (_, *const i32) = components_from_dword32(dword);
+            assert_eq!(count, count_);
+            assert_eq!(unsafe { *data_ }, *data);
+        }
+    }
+
     #[test]
     fn prev_() {
         assert_eq!(prev(9, 10), 8);